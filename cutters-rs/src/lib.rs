@@ -37,7 +37,7 @@
 
 mod parsers;
 
-use parsers::{baseline, croatian, english};
+use parsers::{baseline, biomedical_english, croatian, english};
 
 #[derive(Debug)]
 pub struct Quote<'a> {
@@ -58,12 +58,14 @@ pub enum Language {
     Baseline,
     Croatian,
     English,
+    BiomedicalEnglish,
 }
 
-pub fn cut(text: &str, language: Language) -> Vec<Sentence> {
+pub fn cut(text: &str, language: Language) -> Vec<Sentence<'_>> {
     match language {
         Language::Baseline => baseline::cut(text),
         Language::Croatian => croatian::cut(text),
         Language::English => english::cut(text),
+        Language::BiomedicalEnglish => biomedical_english::cut(text),
     }
 }
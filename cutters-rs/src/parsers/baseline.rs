@@ -6,7 +6,7 @@ use pest_derive::*;
 #[grammar = "../res/baseline.pest"]
 pub struct BaselineParser;
 
-pub fn cut(text: &str) -> Vec<Sentence> {
+pub fn cut(text: &str) -> Vec<Sentence<'_>> {
     let ast = BaselineParser::parse(Rule::sentence_list, text).unwrap();
 
     let mut sentences = vec![];
@@ -0,0 +1,104 @@
+use crate::{Quote, Sentence};
+use pest::Parser;
+use pest_derive::*;
+
+#[derive(Parser)]
+#[grammar = "../res/en_base.pest"]
+#[grammar = "../res/en_biomed.pest"]
+pub struct BiomedicalEnglishParser;
+
+pub fn cut(text: &str) -> Vec<Sentence<'_>> {
+    let ast = BiomedicalEnglishParser::parse(Rule::sentence_list, text).unwrap();
+
+    let mut sentences = vec![];
+
+    for sentence in ast {
+        let str = sentence.as_str();
+        let mut quotes = vec![];
+
+        for quote in sentence.into_inner() {
+            let str = quote.as_str();
+            let mut sentences = vec![];
+
+            for sentence in quote.into_inner() {
+                sentences.push(sentence.as_str());
+            }
+
+            quotes.push(Quote { str, sentences });
+        }
+
+        sentences.push(Sentence { str, quotes });
+    }
+
+    sentences
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // The sentences are written after the text of PMC articles, with the
+    // citations, figure panels, statistics, species and routes they use,
+    // but are not quoted from particular ones.
+    fn check(query_sentences: Vec<&str>) {
+        let text = query_sentences.join(" ");
+
+        let sentences = cut(&text);
+
+        assert!(sentences.len() == query_sentences.len());
+        for (sentence, query_sentence) in sentences.iter().zip(query_sentences) {
+            assert!(query_sentence == sentence.str);
+        }
+    }
+
+    #[test]
+    fn references() {
+        check(vec![
+            r#"The lesions measured approx. 5 mm in diameter (Fig. S2)."#,
+            r#"The rate follows from Eq. 3, as described in Ref. 12."#,
+            r#"The primers are listed in Tab. 2 and Suppl. Fig. 4."#,
+            r#"Patient characteristics are given in Suppl. Table 1."#,
+            r#"Both groups responded (Figs. 2 and 3), cf. Smith et al. for an earlier study."#,
+            r#"Combining Eqs. 4 and 5 gives the final estimate."#,
+            r#"The yields were 40% and 65%, resp."#,
+            r#"Concentrations of 2 and 5 mM resp. were used in the assay."#,
+        ]);
+    }
+
+    #[test]
+    fn citations() {
+        check(vec![
+            r#"As reported by Smith et al. (2010), the effect was small."#,
+            r#"The staining is shown in Fig. 2B and the quantification in Fig. 2C."#,
+            r#"Expression increased 2.5-fold (p < 0.001, Fig. 3A)."#,
+            r#"The samples were analysed as described previously (Fig. S1A)."#,
+            r#"The treated group lost weight (n = 5; p < 0.01)."#,
+            r#"Data are mean ± s.d. of three experiments."#,
+        ]);
+    }
+
+    #[test]
+    fn species() {
+        check(vec![
+            r#"Strains of E. coli and S. aureus were grown overnight."#,
+            r#"Bacteria (E. coli K-12) were grown in LB."#,
+            r#"E. coli was used as the host."#,
+            r#"Several Bacillus spp. were isolated from the soil samples."#,
+            r#"We describe Streptomyces tundrae sp. nov. from arctic soil."#,
+            r#"Brassica oleracea var. italica was used as the host."#,
+        ]);
+    }
+
+    #[test]
+    fn dosage() {
+        check(vec![
+            r#"Mice received the drug i.v. or s.c. at 10 mg/kg."#,
+            r#"Mice were injected i.p. with 100 mg/kg cisplatin."#,
+            r#"Patients took 500 mg p.o. b.i.d. for two weeks."#,
+            r#"Cells were incubated for 10 min."#,
+            r#"After washing, the pellet was resuspended."#,
+            r#"The compound was given i.p."#,
+            r#"The mice survived."#,
+        ]);
+    }
+}
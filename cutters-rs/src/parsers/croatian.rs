@@ -6,7 +6,7 @@ use pest_derive::*;
 #[grammar = "../res/hr.pest"]
 pub struct CroatianParser;
 
-pub fn cut(text: &str) -> Vec<Sentence> {
+pub fn cut(text: &str) -> Vec<Sentence<'_>> {
     let ast = CroatianParser::parse(Rule::sentence_list, text).unwrap();
 
     let mut sentences = vec![];
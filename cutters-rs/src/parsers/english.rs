@@ -3,10 +3,11 @@ use pest::Parser;
 use pest_derive::*;
 
 #[derive(Parser)]
+#[grammar = "../res/en_base.pest"]
 #[grammar = "../res/en.pest"]
 pub struct EnglishParser;
 
-pub fn cut(text: &str) -> Vec<Sentence> {
+pub fn cut(text: &str) -> Vec<Sentence<'_>> {
     let ast = EnglishParser::parse(Rule::sentence_list, text).unwrap();
 
     let mut sentences = vec![];
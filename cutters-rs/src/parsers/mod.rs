@@ -1,3 +1,4 @@
 pub mod baseline;
+pub mod biomedical_english;
 pub mod croatian;
 pub mod english;
@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::json::{Annotation, Relation, RelationNode};
+
+// ===========================================================================
+
+/// The BioC annotations, like the PubTator genes, diseases, chemicals
+/// and species, on the paragraphs and sentences we output. Their
+/// locations are document offsets, which are taken over to positions
+/// in the text of the paragraph or sentence they are in, after
+/// cleaning. An annotation that spans two sentences, or of which
+/// cleaning removed a part, is left out. The relations refer to the
+/// annotations by their ids.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct OutputAnnotation {
+    pub id: String,
+    /// Gene, Disease, Chemical, Species, ... from the infons.
+    #[serde(default)]
+    pub r#type: Option<String>,
+    /// Like the NCBI Gene ID or MeSH ID, from the infons.
+    #[serde(default)]
+    pub identifier: Option<String>,
+    pub text: String,
+    /// Characters in the paragraph or sentence text, the end excluded.
+    pub start: u32,
+    pub end: u32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OutputRelation {
+    pub id: String,
+    #[serde(default)]
+    pub r#type: Option<String>,
+    /// All the infons, like score, role1 and role2 in PubTator.
+    #[serde(default)]
+    pub infons: BTreeMap<String, String>,
+    pub nodes: Vec<RelationNode>,
+}
+
+/// A location of an annotation in the characters of the passage text.
+#[derive(Debug)]
+pub struct Span<'a> {
+    start: usize,
+    end: usize,
+    annotation: &'a Annotation,
+}
+
+/// The locations that are in the passage, as positions in its cleaned
+/// text. The positions map the cleaned text onto the original one, as
+/// returned by Cleaner::clean_mapped(), or None without cleaning.
+pub fn passage_spans<'a, I>(annotations: I, passage_offset: u32, length: usize, positions: Option<&[usize]>) -> Vec<Span<'a>>
+where
+    I: IntoIterator<Item = &'a Annotation>
+{
+    let mut spans = vec![];
+    for annotation in annotations {
+        for location in &annotation.locations {
+            // The offsets come from the file, a bogus length is skipped
+            // rather than overflowing.
+            let Some(start) = location.offset.checked_sub(passage_offset) else {
+                continue;
+            };
+            let Some(end) = start.checked_add(location.length) else {
+                continue;
+            };
+            let (start, end) = (start as usize, end as usize);
+            if location.length == 0 || end > length {
+                continue;
+            }
+            let span = match positions {
+                None => Some((start, end)),
+                Some(positions) => cleaned_span(positions, start, end),
+            };
+            if let Some((start, end)) = span {
+                spans.push(Span { start, end, annotation });
+            }
+        }
+    }
+    spans.sort_by_key(|span| (span.start, span.end));
+    spans
+}
+
+// Where the original start..end is in the cleaned text, if all of it
+// is still there.
+fn cleaned_span(positions: &[usize], start: usize, end: usize) -> Option<(usize, usize)> {
+    let first = positions.partition_point(|&p| p < start);
+    let last = first + (end - start);
+    if last > positions.len() {
+        return None;
+    }
+    let kept = positions[first..last].iter().enumerate().all(|(n, &p)| p == start + n);
+    kept.then_some((first, last))
+}
+
+/// The annotations within from..to of the passage, with their positions
+/// in the text taken from there.
+pub fn anchor(spans: &[Span], from: usize, to: usize, text: &str) -> Vec<OutputAnnotation> {
+    spans.iter()
+        .filter(|span| span.start >= from && span.end <= to)
+        .map(|span| {
+            let infon = |key: &str| span.annotation.infons.get(key).cloned().flatten().filter(|v| !v.is_empty());
+            OutputAnnotation {
+                id: span.annotation.id.clone(),
+                r#type: infon("type"),
+                identifier: infon("identifier").or_else(|| infon("Identifier")),
+                text: text.chars().skip(span.start - from).take(span.end - span.start).collect(),
+                start: (span.start - from) as u32,
+                end: (span.end - from) as u32,
+            }
+        })
+        .collect()
+}
+
+/// The relations between the annotations we output, or between those
+/// relations. A relation with a node that refers to anything else, like
+/// an annotation in a section we left out, is left out too.
+pub fn kept_relations(relations: Vec<OutputRelation>, annotation_ids: HashSet<&str>) -> Vec<OutputRelation> {
+    let mut known = annotation_ids;
+    let mut kept = vec![false; relations.len()];
+    loop {
+        let mut changed = false;
+        for (relation, kept) in relations.iter().zip(kept.iter_mut()) {
+            if !*kept && !relation.nodes.is_empty() && relation.nodes.iter().all(|node| known.contains(node.refid.as_str())) {
+                *kept = true;
+                known.insert(&relation.id);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    relations.into_iter().zip(kept).filter(|(_, kept)| *kept).map(|(relation, _)| relation).collect()
+}
+
+pub fn output_relation(relation: &Relation) -> OutputRelation {
+    let infons: BTreeMap<String, String> = relation.infons.iter()
+        .filter_map(|(key, value)| Some((key.clone(), value.clone()?)))
+        .collect();
+    OutputRelation {
+        id: relation.id.clone(),
+        r#type: infons.get("type").cloned(),
+        infons,
+        nodes: relation.nodes.clone(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::json::Location;
+
+    fn annotation(id: &str, offset: u32, length: u32) -> Annotation {
+        Annotation {
+            id: id.to_string(),
+            infons: HashMap::from([("type".to_string(), Some("Gene".to_string()))]),
+            text: String::new(),
+            locations: vec![Location { offset, length }],
+        }
+    }
+
+    #[test]
+    fn anchored() {
+        // "BRCA1 [3] binds TP53." at offset 100, cleaned to "BRCA1 binds TP53."
+        let annotations = [annotation("1", 100, 5), annotation("2", 116, 4), annotation("3", 106, 3),
+                           annotation("4", 101, u32::MAX)];
+        let mut positions: Vec<usize> = (0..5).collect();
+        positions.extend(9..=21);
+        let spans = passage_spans(&annotations, 100, 21, Some(&positions));
+        // The citation was removed, and its annotation with it.
+        assert!(spans.len() == 2);
+
+        let found = anchor(&spans, 0, 17, "BRCA1 binds TP53.");
+        assert!(found[0].text == "BRCA1" && found[0].start == 0 && found[0].end == 5);
+        assert!(found[1].text == "TP53" && found[1].start == 12);
+        assert!(found[1].r#type.as_deref() == Some("Gene"));
+
+        // The second "sentence" starts at "binds".
+        let found = anchor(&spans, 6, 17, "binds TP53.");
+        assert!(found.len() == 1 && found[0].start == 6 && found[0].text == "TP53");
+    }
+
+    #[test]
+    fn relations() {
+        let relation = |id: &str, refids: &[&str]| OutputRelation {
+            id: id.to_string(),
+            r#type: None,
+            infons: BTreeMap::new(),
+            nodes: refids.iter().map(|refid| RelationNode { refid: refid.to_string(), role: String::new() }).collect(),
+        };
+        let relations = vec![relation("R1", &["1", "2"]), relation("R2", &["1", "zz"]),
+                             relation("R3", &["R4", "1"]), relation("R4", &["2"]), relation("R5", &["R2"])];
+        let kept = kept_relations(relations, HashSet::from(["1", "2"]));
+        let ids: Vec<&str> = kept.iter().map(|relation| relation.id.as_str()).collect();
+        assert!(ids == ["R1", "R3", "R4"]);
+    }
+}
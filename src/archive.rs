@@ -0,0 +1,100 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread;
+
+use flate2::read::MultiGzDecoder;
+
+use anyhow::Result;
+
+// ===========================================================================
+
+/*
+    PMC ships the BioC and Open Access packages as tarballs. Instead of
+    unpacking them to disk, we read the members one by one and hand
+    them out for parallel processing.
+*/
+
+// Number of members read ahead of the workers, so we don't decompress
+// the whole archive into memory when parsing is slower than reading.
+const READ_AHEAD: usize = 256;
+
+pub struct Member {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+// The OA packages come with the images, PDFs and media of the article,
+// which we don't want to decompress, let alone parse.
+const MEMBER_EXTENSIONS: &[&str] = &[".xml", ".nxml", ".json"];
+
+pub fn is_article_member(name: &str) -> bool {
+    let name = name.to_lowercase();
+    let name = name.strip_suffix(".gz").unwrap_or(&name);
+    MEMBER_EXTENSIONS.iter().any(|extension| name.ends_with(extension))
+}
+
+pub fn is_tar<P: AsRef<Path>>(path: P) -> bool {
+    let name = path.as_ref().to_string_lossy();
+    name.ends_with(".tar") || name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+// The article files in the archive, read on a separate thread. Use
+// par_bridge() on the receiver to process them in parallel.
+pub fn tar_members(path: PathBuf) -> Receiver<Result<Member>> {
+    let (sender, receiver) = sync_channel(READ_AHEAD);
+
+    thread::spawn(move || {
+        if let Err(e) = read_tar(&path, &sender) {
+            let _ = sender.send(Err(e));
+        }
+    });
+
+    receiver
+}
+
+fn read_tar(path: &Path, sender: &SyncSender<Result<Member>>) -> Result<()> {
+    let file = BufReader::new(File::open(path)?);
+    let reader: Box<dyn Read> = if path.to_string_lossy().ends_with(".tar") {
+        Box::new(file)
+    } else {
+        Box::new(MultiGzDecoder::new(file))
+    };
+
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if !is_article_member(&name) {
+            continue;
+        }
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data)?;
+
+        // The receiver is gone if the workers stopped early.
+        if sender.send(Ok(Member { name, data })).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn members() {
+        assert!(is_article_member("PMC176545/gkg001.nxml"));
+        assert!(is_article_member("BioCXML/PMC176545.xml.gz"));
+        assert!(is_article_member("PMC176545.JSON"));
+        assert!(!is_article_member("PMC176545/gkg001f1.jpg"));
+        assert!(!is_article_member("PMC176545/gkg001.pdf"));
+        assert!(!is_article_member("PMC176545/media-1.xml.zip"));
+    }
+}
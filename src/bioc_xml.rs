@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use crate::json::{Annotation, Document, Location, Passage, Relation, RelationNode, Root, Sentence};
+use crate::xml::Element;
+
+// ===========================================================================
+
+/*
+    BioC XML reader. Builds the same Root/Document/Passage structure as
+    the BioC JSON files, so the rest of the extraction doesn't need to
+    know which serialization it was given.
+
+    <collection>
+      <source/> <date/> <key/> <infon key=".."/>*
+      <document>
+        <id/> <infon/>*
+        <passage> <infon/>* <offset/> <text/> <sentence/>* <annotation/>* <relation/>* </passage>*
+        <annotation/>* <relation/>*
+      </document>*
+    </collection>
+
+    <sentence> <infon/>* <offset/> <text/> <annotation/>* <relation/>* </sentence>
+    <annotation id=".."> <infon/>* <location offset=".." length=".."/>* <text/> </annotation>
+    <relation id=".."> <infon/>* <node refid=".." role=".."/>* </relation>
+*/
+pub fn parse_bioc_collection(collection: &Element) -> Result<Root> {
+    let documents = collection.children_named("document")
+        .map(parse_document)
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Root {
+        source: child_text(collection, "source"),
+        date: child_text(collection, "date"),
+        infons: parse_infons(collection),
+        documents,
+    })
+}
+
+fn parse_document(document: &Element) -> Result<Document> {
+    let passages = document.children_named("passage")
+        .map(parse_passage)
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Document {
+        id: child_text(document, "id"),
+        infons: parse_infons(document),
+        passages,
+        annotations: parse_annotations(document)?,
+        relations: parse_relations(document),
+    })
+}
+
+fn parse_passage(passage: &Element) -> Result<Passage> {
+    let offset = child_text(passage, "offset");
+    let offset = offset.trim().parse::<u32>()
+        .map_err(|e| anyhow!("invalid passage offset {:?}: {}", offset, e))?;
+
+    let sentences = passage.children_named("sentence")
+        .map(parse_sentence)
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Passage {
+        offset,
+        infons: parse_infons(passage),
+        text: child_text(passage, "text"),
+        sentences,
+        annotations: parse_annotations(passage)?,
+        relations: parse_relations(passage),
+    })
+}
+
+fn parse_sentence(sentence: &Element) -> Result<Sentence> {
+    let offset = child_text(sentence, "offset");
+    let offset = offset.trim().parse::<u32>()
+        .map_err(|e| anyhow!("invalid sentence offset {:?}: {}", offset, e))?;
+
+    Ok(Sentence {
+        offset,
+        infons: parse_infons(sentence),
+        text: child_text(sentence, "text"),
+        annotations: parse_annotations(sentence)?,
+        relations: parse_relations(sentence),
+    })
+}
+
+fn parse_annotations(element: &Element) -> Result<Vec<Annotation>> {
+    element.children_named("annotation")
+        .map(|annotation| {
+            let locations = annotation.children_named("location")
+                .map(|location| {
+                    let number = |key: &str| {
+                        let value = location.attr(key).unwrap_or("");
+                        value.trim().parse::<u32>()
+                            .map_err(|e| anyhow!("invalid annotation {} {:?}: {}", key, value, e))
+                    };
+                    Ok(Location { offset: number("offset")?, length: number("length")? })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Annotation {
+                id: annotation.attr("id").unwrap_or("").to_string(),
+                infons: parse_infons(annotation),
+                text: child_text(annotation, "text"),
+                locations,
+            })
+        })
+        .collect()
+}
+
+fn parse_relations(element: &Element) -> Vec<Relation> {
+    element.children_named("relation")
+        .map(|relation| Relation {
+            id: relation.attr("id").unwrap_or("").to_string(),
+            infons: parse_infons(relation),
+            nodes: relation.children_named("node")
+                .map(|node| RelationNode {
+                    refid: node.attr("refid").unwrap_or("").to_string(),
+                    role: node.attr("role").unwrap_or("").to_string(),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+fn parse_infons(element: &Element) -> HashMap<String, Option<String>> {
+    element.children_named("infon")
+        .filter_map(|infon| {
+            infon.attr("key").map(|key| (key.to_string(), Some(infon.text())))
+        })
+        .collect()
+}
+
+// Missing elements are treated as empty, like the JSON files do.
+fn child_text(element: &Element, name: &str) -> String {
+    element.child(name).map(|e| e.text()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::xml;
+
+    #[test]
+    fn collection() {
+        let data = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE collection SYSTEM "BioC.dtd">
+<collection><source>PMC</source><date>20231106</date><key>pmc.key</key>
+<document><id>10546722</id>
+<passage><infon key="section_type">TITLE</infon><infon key="type">front</infon><infon key="year">2023</infon><offset>0</offset><text>Cerium oxide &amp; tendons</text></passage>
+<passage><infon key="section_type">INTRO</infon><infon key="type">paragraph</infon><offset>25</offset><text>DNA methylation is an epigenetic modification.</text>
+<annotation id="1"><infon key="type">Gene</infon><location offset="25" length="3"/><text>DNA</text></annotation></passage>
+<relation id="R1"><infon key="type">Association</infon><node refid="1" role="Gene"/></relation>
+</document></collection>"#;
+
+        let root = parse_bioc_collection(&xml::parse(data).unwrap()).unwrap();
+
+        assert!(root.source == "PMC");
+        assert!(root.documents.len() == 1);
+
+        let passages = &root.documents[0].passages;
+        assert!(passages.len() == 2);
+        assert!(passages[0].text == "Cerium oxide & tendons");
+        assert!(passages[0].infons["year"] == Some("2023".to_string()));
+        assert!(passages[1].offset == 25);
+        assert!(passages[1].infons["section_type"] == Some("INTRO".to_string()));
+        assert!(passages[1].annotations[0].locations == [Location { offset: 25, length: 3 }]);
+        assert!(root.documents[0].relations[0].nodes[0].refid == "1");
+    }
+}
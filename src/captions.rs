@@ -0,0 +1,209 @@
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::json::OutputParagraph;
+
+// ===========================================================================
+
+/// Figure and table captions, with the paragraphs that mention them.
+/// The BioC files have a fig_caption passage for every figure, and
+/// sometimes a fig_title_caption before it, with the figure id like
+/// "F3" in the infons. The JATS reader also adds the label, "Figure 3".
+///
+/// A paragraph mentions a figure when it says "Fig. 3", "Figures 2 and
+/// 3" or "Figs. 2-4", and the figure has number 3, from its label or
+/// else from the digits at the end of its id. Supplementary figures,
+/// "Figure S1", are not matched.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Caption {
+    /// "fig" or "table".
+    pub kind: String,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub label: Option<String>,
+    pub text: String,
+    /// Position of the caption in the document, in characters.
+    #[serde(default)]
+    pub offset: u32,
+    #[serde(default)]
+    pub length: u32,
+    #[serde(default)]
+    pub mentions: Vec<OutputParagraph>,
+}
+
+/// Collects the captions and the candidate paragraphs of an article, and
+/// links them at the end.
+#[derive(Default)]
+pub struct CaptionCollector {
+    captions: Vec<Caption>,
+    // The paragraphs with the figures and tables they mention.
+    paragraphs: Vec<(BTreeSet<(&'static str, u32)>, OutputParagraph)>,
+}
+
+impl CaptionCollector {
+    /// A fig_caption or table_caption passage, or their title_caption. A
+    /// title and caption with the same id become one caption.
+    pub fn add_caption(&mut self, kind: &str, id: Option<String>, label: Option<String>, text: &str, offset: u32) {
+        let length = text.chars().count() as u32;
+        if let Some(last) = self.captions.last_mut() {
+            if last.kind == kind && id.is_some() && last.id == id {
+                last.text = format!("{} {}", last.text, text).trim().to_string();
+                last.length = offset + length - last.offset;
+                last.label = last.label.take().or(label);
+                return;
+            }
+        }
+        let label = label.or_else(|| caption_label(text));
+        self.captions.push(Caption {
+            kind: kind.to_string(),
+            id,
+            label,
+            text: text.to_string(),
+            offset,
+            length,
+            mentions: vec![],
+        });
+    }
+
+    /// A paragraph is kept when it mentions any figure or table.
+    pub fn add_paragraph(&mut self, paragraph: OutputParagraph) {
+        let mentioned = mentions(&paragraph.text);
+        if !mentioned.is_empty() {
+            self.paragraphs.push((mentioned, paragraph));
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<Caption> {
+        for caption in &mut self.captions {
+            let kind = caption.kind.as_str();
+            let Some(number) = caption_number(caption) else {
+                continue;
+            };
+            caption.mentions = self.paragraphs.iter()
+                .filter(|(mentioned, _)| mentioned.contains(&(kind, number)))
+                .map(|(_, paragraph)| paragraph.clone())
+                .collect();
+        }
+        self.captions
+    }
+}
+
+// "Fig. 2a", "Figures 2 and 3", "Tables 1-3", with the numbers after
+// the first one in the second group.
+fn mention_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"\b(Fig(?:ure)?s?\.?|Tables?)\s*(\d+[a-zA-Z]?(?:\s*(?:,|and|&|-|\u{2013})\s*\d+[a-zA-Z]?\b)*)").unwrap()
+    })
+}
+
+// The figures and tables a text mentions, ranges expanded.
+fn mentions(text: &str) -> BTreeSet<(&'static str, u32)> {
+    let mut found = BTreeSet::new();
+    for captures in mention_regex().captures_iter(text) {
+        let kind = if captures[1].starts_with("Fig") { "fig" } else { "table" };
+        let list = &captures[2];
+        let mut previous: Option<u32> = None;
+        let mut range = false;
+        let mut rest = list;
+        while !rest.is_empty() {
+            let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+            if digits.is_empty() {
+                let c = rest.chars().next().unwrap();
+                if c == '-' || c == '\u{2013}' {
+                    range = true;
+                }
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+            rest = &rest[digits.len()..];
+            let Ok(number) = digits.parse::<u32>() else {
+                continue;
+            };
+            match previous {
+                // Figs. 2-40 is a typo more often than not.
+                Some(from) if range && from < number && number - from <= 20 => {
+                    found.extend((from + 1..=number).map(|n| (kind, n)));
+                }
+                _ => {
+                    found.insert((kind, number));
+                }
+            }
+            previous = Some(number);
+            range = false;
+        }
+    }
+    found
+}
+
+// The "Figure 3" or "Table 1" a caption starts with, if any.
+fn caption_label(text: &str) -> Option<String> {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    let regex = REGEX.get_or_init(|| Regex::new(r"^(?:Fig(?:ure)?\.?|Table)\s*\d+[a-zA-Z]?").unwrap());
+    regex.find(text.trim_start()).map(|found| found.as_str().to_string())
+}
+
+// The first number in the label, or else the one the id ends with.
+fn caption_number(caption: &Caption) -> Option<u32> {
+    if let Some(label) = &caption.label {
+        let digits: String = label.chars()
+            .skip_while(|c| !c.is_ascii_digit())
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        // "Figure S1" has no number of its own.
+        if digits.is_empty() || label.contains(&format!("S{}", digits)) {
+            return None;
+        }
+        return digits.parse().ok();
+    }
+    let id = caption.id.as_deref()?;
+    let digits: String = id.chars().rev().take_while(|c| c.is_ascii_digit()).collect();
+    digits.chars().rev().collect::<String>().parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn paragraph(text: &str) -> OutputParagraph {
+        OutputParagraph {
+            r#type: "RESULTS".to_string(),
+            passage_type: "paragraph".to_string(),
+            text: text.to_string(),
+            headings: vec![],
+            paragraph: 1,
+            offset: 0,
+            length: text.chars().count() as u32,
+            annotations: vec![],
+            segmentation: None,
+        }
+    }
+
+    #[test]
+    fn mentioned() {
+        let found = mentions("As in Figs. 2-4 and Table 1, and Figure 6b, not Figure S1.");
+        let expected = [("fig", 2), ("fig", 3), ("fig", 4), ("fig", 6), ("table", 1)];
+        assert!(found.into_iter().collect::<Vec<_>>() == expected);
+        assert!(mentions("Figures 1, 5 and 7").len() == 3);
+    }
+
+    #[test]
+    fn linked() {
+        let mut collector = CaptionCollector::default();
+        collector.add_caption("fig", Some("F3".to_string()), None, "Growth curves.", 100);
+        collector.add_caption("fig", Some("F3".to_string()), None, "Cells were grown for 3 days.", 115);
+        collector.add_caption("table", Some("T1".to_string()), Some("Table 1".to_string()), "Patients.", 200);
+        collector.add_paragraph(paragraph("Cells grew (Fig. 3)."));
+        collector.add_paragraph(paragraph("No figures here."));
+        let captions = collector.finish();
+        assert!(captions.len() == 2);
+        assert!(captions[0].text == "Growth curves. Cells were grown for 3 days.");
+        assert!(captions[0].length == 43);
+        assert!(captions[0].mentions.len() == 1);
+        assert!(captions[1].label.as_deref() == Some("Table 1") && captions[1].mentions.is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use anyhow::{anyhow, Result};
+
+// ===========================================================================
+
+/*
+    Cleaning of the paragraph texts, before they are split into
+    sentences. Every rule is a regular expression with a replacement,
+    and is known by its name, so it can be switched on by itself and
+    the number of characters it removed can be counted.
+
+    Besides the built-in rules, rules can be read from a TOML or YAML
+    file, and are applied after the built-in ones, in file order:
+
+    [[rules]]
+    name = "trial-ids"
+    pattern = 'NCT\d{8}'
+    sections = ["ABSTRACT", "METHODS"]
+
+    The replacement defaults to "", and can refer to groups like $1.
+    Without sections, a rule is applied to all of them.
+*/
+
+// The built-in rules, in the order they are applied. The leading
+// whitespace is taken along, so "shown (Fig. 2)." becomes "shown.".
+const BUILTIN_RULES: [(&str, &str, &str); 5] = [
+    // LaTeX source pasted into the text, (?s) to match over lines.
+    ("latex", r"(?s)\\documentclass.*?\\end\{document\}", ""),
+    // (Fig. 2), (Figure 3b), (Figs. 4).
+    ("figures", r"\s*\(Fig(?:ure|\.)?s?\.? ?\d+[a-zA-Z]?\)", ""),
+    // Numeric citations like [3], [1, 4] and [5-7].
+    ("citations", r"\s*\[\s*\d+\s*(?:[,\u{2013}-]\s*\d+\s*)*\]", ""),
+    // Short parentheticals, longer ones take too much.
+    ("parens", r"\s*\([^)]{1,10}\)", ""),
+    // Footnote numbers at the start of a line.
+    ("footnotes", r"\n\d{1,2}\b", "\n"),
+];
+
+#[derive(Debug, Clone)]
+pub struct CleanRule {
+    pub name: String,
+    pub regex: Regex,
+    pub replacement: String,
+    /// Section types the rule applies to, all of them when empty.
+    pub sections: BTreeSet<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    rules: Vec<RuleEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleEntry {
+    name: Option<String>,
+    pattern: String,
+    #[serde(default)]
+    replacement: String,
+    #[serde(default)]
+    sections: Vec<String>,
+}
+
+/// The cleaning rules applied to the paragraphs, in order.
+#[derive(Debug, Clone, Default)]
+pub struct Cleaner {
+    pub rules: Vec<CleanRule>,
+}
+
+impl Cleaner {
+    /// The named built-in rules, "all" for all of them. They are always
+    /// applied in the order above, whatever the order of the names.
+    pub fn builtin<S: AsRef<str>>(names: &[S]) -> Result<Cleaner> {
+        for name in names {
+            let name = name.as_ref();
+            if name != "all" && !BUILTIN_RULES.iter().any(|(rule, _, _)| *rule == name) {
+                let names: Vec<&str> = BUILTIN_RULES.iter().map(|(rule, _, _)| *rule).collect();
+                return Err(anyhow!("unknown cleaning rule {:?}, use one of {} or all",
+                                   name, names.join(", ")));
+            }
+        }
+        let all = names.iter().any(|name| name.as_ref() == "all");
+
+        let rules = BUILTIN_RULES.iter()
+            .filter(|(name, _, _)| all || names.iter().any(|n| n.as_ref() == *name))
+            .map(|(name, pattern, replacement)| CleanRule {
+                name: name.to_string(),
+                regex: Regex::new(pattern).unwrap(),
+                replacement: replacement.to_string(),
+                sections: BTreeSet::new(),
+            })
+            .collect();
+        Ok(Cleaner { rules })
+    }
+
+    /// Adds the rules from a .toml, .yaml or .yml file. All patterns are
+    /// checked here, so a bad rule stops us before any file is read.
+    pub fn load_rules<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        let file: RulesFile = match extension {
+            "toml" => toml::from_str(&data).map_err(|e| anyhow!("{}: {}", path.display(), e))?,
+            "yaml" | "yml" => serde_yaml::from_str(&data).map_err(|e| anyhow!("{}: {}", path.display(), e))?,
+            _ => return Err(anyhow!("{}: rules files should end in .toml, .yaml or .yml", path.display())),
+        };
+
+        for (number, entry) in file.rules.into_iter().enumerate() {
+            // Rules without a name are known by file and number.
+            let label = entry.name.clone().unwrap_or_else(|| format!("rule {}", number + 1));
+            let regex = Regex::new(&entry.pattern)
+                .map_err(|e| anyhow!("{}: invalid pattern in {}:\n{}", path.display(), label, e))?;
+            if entry.sections.iter().any(|section| section.trim().is_empty()) {
+                return Err(anyhow!("{}: empty section type in {}", path.display(), label));
+            }
+            let name = entry.name.unwrap_or_else(|| {
+                let file = path.file_name().unwrap_or_default().to_string_lossy();
+                format!("{}:{}", file, number + 1)
+            });
+            self.rules.push(CleanRule {
+                name,
+                regex,
+                replacement: entry.replacement,
+                sections: entry.sections.into_iter().collect(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Applies the rules for the section type in order, and adds the
+    /// number of characters each of them removed to the counts.
+    pub fn clean(&self, text: &str, section_type: &str, removed: &mut BTreeMap<String, usize>) -> String {
+        self.clean_mapped(text, section_type, removed).0
+    }
+
+    /// The same, with for every character of the cleaned text the
+    /// position of the character it came from in the original text, and
+    /// one more for the end of the text. Positions are in characters.
+    /// Replacement text gets the position of the text it replaced.
+    pub fn clean_mapped(&self, text: &str, section_type: &str, removed: &mut BTreeMap<String, usize>) -> (String, Vec<usize>) {
+        let mut text = text.to_string();
+        let mut positions: Vec<usize> = (0..=text.chars().count()).collect();
+
+        for rule in &self.rules {
+            if !rule.sections.is_empty() && !rule.sections.contains(section_type) {
+                continue;
+            }
+            if !rule.regex.is_match(&text) {
+                continue;
+            }
+
+            let mut cleaned = String::with_capacity(text.len());
+            let mut cleaned_positions = Vec::with_capacity(positions.len());
+            let mut last = 0; // bytes
+            let mut index = 0; // characters
+            for captures in rule.regex.captures_iter(&text) {
+                let found = captures.get(0).unwrap();
+                for c in text[last..found.start()].chars() {
+                    cleaned.push(c);
+                    cleaned_positions.push(positions[index]);
+                    index += 1;
+                }
+                let mut replacement = String::new();
+                captures.expand(&rule.replacement, &mut replacement);
+                for c in replacement.chars() {
+                    cleaned.push(c);
+                    cleaned_positions.push(positions[index]);
+                }
+                index += found.as_str().chars().count();
+                last = found.end();
+            }
+            for c in text[last..].chars() {
+                cleaned.push(c);
+                cleaned_positions.push(positions[index]);
+                index += 1;
+            }
+            cleaned_positions.push(positions[index]);
+
+            let before = positions.len();
+            let after = cleaned_positions.len();
+            if before > after {
+                *removed.entry(rule.name.clone()).or_default() += before - after;
+            }
+            text = cleaned;
+            positions = cleaned_positions;
+        }
+        (text, positions)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn builtin() {
+        let cleaner = Cleaner::builtin(&["all"]).unwrap();
+        let mut removed = BTreeMap::new();
+        let text = cleaner.clean("Cells grew (Fig. 2a) as shown before [3, 4-6].", "INTRO", &mut removed);
+        assert!(text == "Cells grew as shown before.");
+        assert!(removed["figures"] == 10);
+        assert!(removed["citations"] == 9);
+        assert!(!removed.contains_key("parens"));
+    }
+
+    #[test]
+    fn selected() {
+        let cleaner = Cleaner::builtin(&["citations"]).unwrap();
+        let mut removed = BTreeMap::new();
+        let text = cleaner.clean("Shown (Fig. 2) before [3].", "INTRO", &mut removed);
+        assert!(text == "Shown (Fig. 2) before.");
+        assert!(Cleaner::builtin(&["tables"]).is_err());
+    }
+
+    #[test]
+    fn rules_file() {
+        let dir = std::env::temp_dir().join(format!("pubmed-rs-rules-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let toml = dir.join("rules.toml");
+        fs::write(&toml, r#"
+            [[rules]]
+            name = "trial-ids"
+            pattern = '\s*\(?NCT\d{8}\)?'
+            sections = ["METHODS"]
+
+            [[rules]]
+            pattern = '[\w.]+@[\w.]+'
+            replacement = "<email>"
+        "#).unwrap();
+        let mut cleaner = Cleaner::default();
+        cleaner.load_rules(&toml).unwrap();
+        let mut removed = BTreeMap::new();
+        let text = "Registered (NCT01234567), mail x@y.org";
+        assert!(cleaner.clean(text, "METHODS", &mut removed) == "Registered, mail <email>");
+        assert!(cleaner.clean(text, "INTRO", &mut removed) == "Registered (NCT01234567), mail <email>");
+        assert!(removed["trial-ids"] == 14);
+        assert!(cleaner.rules[1].name == "rules.toml:2");
+
+        let yaml = dir.join("rules.yaml");
+        fs::write(&yaml, "rules:\n  - name: broken\n    pattern: '[a-'\n").unwrap();
+        let error = Cleaner::default().load_rules(&yaml).unwrap_err().to_string();
+        assert!(error.contains("invalid pattern in broken"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn positions() {
+        let cleaner = Cleaner::builtin(&["citations", "figures"]).unwrap();
+        let mut removed = BTreeMap::new();
+        let text = "Grew [3] fast (Fig. 2). Then él [4].";
+        let (cleaned, positions) = cleaner.clean_mapped(text, "INTRO", &mut removed);
+        assert!(cleaned == "Grew fast. Then él.");
+        assert!(positions.len() == cleaned.chars().count() + 1);
+        // "fast" is at 5 in the cleaned text and at 9 in the original.
+        assert!(positions[5] == 9);
+        // The final "." follows the removed " [4]".
+        assert!(positions[18] == 35);
+        assert!(positions[19] == text.chars().count());
+    }
+}
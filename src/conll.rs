@@ -0,0 +1,117 @@
+use std::io::{self, Write};
+
+use crate::annotations::OutputAnnotation;
+use crate::json::OutputArticle;
+
+// ===========================================================================
+
+/*
+    CoNLL output for named entity recognition: every sentence is split
+    into tokens, one per line with its BIO tag from the annotations,
+    and a blank line after every sentence. Articles start with a
+    -DOCSTART- line, as in CoNLL-2003.
+
+    -DOCSTART-	O
+
+    BRCA1	B-Gene
+    mutations	O
+    cause	O
+    breast	B-Disease
+    cancer	I-Disease
+    .	O
+
+    Tokens are runs of letters and digits, and every other character
+    that is not whitespace on its own. A token is tagged with the first
+    annotation it overlaps, annotations without a type are "Entity".
+*/
+
+// The tokens of a text, as character positions, the end excluded.
+pub fn tokenize(text: &str) -> Vec<(usize, usize)> {
+    let mut tokens = vec![];
+    let mut word: Option<usize> = None;
+    for (index, c) in text.chars().enumerate() {
+        if c.is_alphanumeric() {
+            word.get_or_insert(index);
+            continue;
+        }
+        if let Some(start) = word.take() {
+            tokens.push((start, index));
+        }
+        if !c.is_whitespace() {
+            tokens.push((index, index + 1));
+        }
+    }
+    if let Some(start) = word {
+        tokens.push((start, text.chars().count()));
+    }
+    tokens
+}
+
+// The tokens of a sentence with their tags.
+pub fn bio_tags(text: &str, annotations: &[OutputAnnotation]) -> Vec<(String, String)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut previous: Option<usize> = None;
+    tokenize(text).into_iter()
+        .map(|(start, end)| {
+            let token: String = chars[start..end].iter().collect();
+            let found = annotations.iter()
+                .position(|a| (a.start as usize) < end && (a.end as usize) > start);
+            let tag = match found {
+                Some(n) => {
+                    let kind = annotations[n].r#type.as_deref().unwrap_or("Entity");
+                    let prefix = if previous == Some(n) { "I" } else { "B" };
+                    format!("{}-{}", prefix, kind)
+                }
+                None => "O".to_string(),
+            };
+            previous = found;
+            (token, tag)
+        })
+        .collect()
+}
+
+// The sentences of an article, or its paragraphs without sentence
+// splitting. With only_annotated, sentences without annotations are
+// left out, and an article without any is left out completely.
+pub fn write_conll<W: Write>(out: &mut W, article: &OutputArticle, only_annotated: bool) -> io::Result<()> {
+    let sentences: Vec<_> = article.sentences.iter()
+        .filter(|sentence| !only_annotated || !sentence.annotations.is_empty())
+        .collect();
+    if sentences.is_empty() && only_annotated {
+        return Ok(());
+    }
+    writeln!(out, "-DOCSTART-\tO\n")?;
+    for sentence in sentences {
+        for (token, tag) in bio_tags(&sentence.text, &sentence.annotations) {
+            writeln!(out, "{}\t{}", token, tag)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn annotation(kind: &str, start: u32, end: u32) -> OutputAnnotation {
+        OutputAnnotation {
+            id: String::new(),
+            r#type: Some(kind.to_string()),
+            identifier: None,
+            text: String::new(),
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn tags() {
+        assert!(tokenize("IL-2 (β) cells.") == [(0, 2), (2, 3), (3, 4), (5, 6), (6, 7), (7, 8), (9, 14), (14, 15)]);
+
+        let text = "BRCA1 mutations cause breast cancer and ovarian cancer.";
+        let annotations = [annotation("Gene", 0, 5), annotation("Disease", 22, 35), annotation("Disease", 40, 54)];
+        let tags: Vec<String> = bio_tags(text, &annotations).into_iter().map(|(_, tag)| tag).collect();
+        assert!(tags == ["B-Gene", "O", "O", "B-Disease", "I-Disease", "O", "B-Disease", "I-Disease", "O"]);
+    }
+}
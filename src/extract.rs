@@ -0,0 +1,352 @@
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+use rayon::prelude::*;
+
+use anyhow::Result;
+
+use crate::clean::Cleaner;
+use crate::filter::ArticleFilter;
+use crate::policy::SectionPolicy;
+use crate::archive::{is_tar, tar_members, Member};
+use crate::json::{extract_json_from_json, extract_json_from_reader, extract_medline, extract_medline_from_reader};
+use crate::json::{is_medline, is_medline_reader, OutputArticle};
+
+// ===========================================================================
+
+/// What to take from the articles. The defaults give the paragraphs of
+/// the main sections, without sentence splitting.
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    /// Section types to keep, like INTRO or RESULTS. By default all
+    /// sections except the references, figures, tables, and other back
+    /// matter are kept.
+    pub sections: SectionPolicy,
+
+    /// BioC passage types to output, "title" takes all the title levels.
+    pub passage_types: Vec<String>,
+
+    /// Split the paragraphs into sentences.
+    pub sentences: bool,
+
+    /// When splitting, take the sentences of the BioC passages that have
+    /// them, and split only the others.
+    pub bioc_sentences: bool,
+
+    /// The sentence splitter, English by default.
+    pub splitter: Splitter,
+
+    /// Cleaning rules applied to the paragraphs before splitting, none
+    /// by default.
+    pub cleaner: Cleaner,
+
+    /// Only articles with this metadata, all of them by default.
+    pub filter: ArticleFilter,
+
+    /// Collect the references, and the figure and table captions with
+    /// the paragraphs that mention them, whatever the section policy.
+    pub references: bool,
+    pub captions: bool,
+
+    /// Rebuild the tables from their markup, whatever the section policy.
+    pub tables: bool,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            sections: SectionPolicy::default(),
+            passage_types: vec!["paragraph".to_string(), "abstract".to_string()],
+            sentences: false,
+            bioc_sentences: false,
+            splitter: Splitter::default(),
+            cleaner: Cleaner::default(),
+            filter: ArticleFilter::default(),
+            references: false,
+            captions: false,
+            tables: false,
+        }
+    }
+}
+
+/// The rules of cutters to split the sentences with. The biomedical
+/// ones also know the abbreviations of scientific articles, like
+/// `Suppl. Fig. 4`, `E. coli` or `i.v.`, and don't end a sentence after
+/// them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Splitter {
+    #[default]
+    English,
+    Biomedical,
+}
+
+impl Splitter {
+    pub fn language(self) -> cutters::Language {
+        match self {
+            Splitter::English => cutters::Language::English,
+            Splitter::Biomedical => cutters::Language::BiomedicalEnglish,
+        }
+    }
+}
+
+/// Every file gives a Vec of articles: a PubMed baseline file or a BioC
+/// collection holds many, a PMC article file one. The format is taken
+/// from the content, gzipped files are decompressed. Tarballs are not
+/// read here, see process_inputs().
+pub fn extract_from_path<P: AsRef<Path>>(file_path: P, options: &ExtractOptions) -> Result<Vec<OutputArticle>> {
+    let mut articles = vec![];
+    extract_each_from_path(file_path, options, |article| articles.push(article))?;
+    Ok(articles)
+}
+
+/// The same, but the articles are passed to each() as they are read, so
+/// a PubMed baseline file with its tens of thousands of citations is
+/// never in memory as a whole.
+pub fn extract_each_from_path<P, F>(file_path: P, options: &ExtractOptions, mut each: F) -> Result<()>
+where
+    P: AsRef<Path>,
+    F: FnMut(OutputArticle)
+{
+    let filename = file_path.as_ref().to_string_lossy().into_owned();
+    extract_file(file_path.as_ref(), &filename, options, &mut each)
+}
+
+/// The same for data in memory. The name is only used in messages.
+pub fn extract_from_bytes(data: &[u8], name: &str, options: &ExtractOptions) -> Result<Vec<OutputArticle>> {
+    let mut articles = vec![];
+    extract_each_from_bytes(data, name, options, |article| articles.push(article))?;
+    Ok(articles)
+}
+
+pub fn extract_each_from_bytes<F: FnMut(OutputArticle)>(data: &[u8], name: &str, options: &ExtractOptions, mut each: F) -> Result<()> {
+    if is_medline_reader(data)? {
+        extract_medline_from_reader(data, name, options, &mut each)
+    } else {
+        extract_json_from_reader(data, name, options)?.into_iter().for_each(each);
+        Ok(())
+    }
+}
+
+fn extract_file(file_path: &Path, filename: &str, options: &ExtractOptions, each: &mut dyn FnMut(OutputArticle)) -> Result<()> {
+    if is_medline(file_path)? {
+        extract_medline(file_path, filename, options, each)
+    } else {
+        extract_json_from_json(file_path, filename, options)?.into_iter().for_each(each);
+        Ok(())
+    }
+}
+
+/// Articles handed on at a time by process_inputs().
+pub const BATCH_SIZE: usize = 1000;
+
+// A unit of work: a file, or a member of a tarball.
+enum Input {
+    File(PathBuf),
+    Member(Member),
+}
+
+// The files with the tarballs replaced by their members, with a name
+// for each. The member name is "archive.tar.gz:path/in/archive.xml".
+// The members are read on a separate thread while we iterate, so this
+// can be handed to par_bridge().
+fn inputs(files: Vec<(PathBuf, String)>) -> impl Iterator<Item = (String, Result<Input>)> {
+    files.into_iter().flat_map(|(file_path, filename)| {
+        let items: Box<dyn Iterator<Item = (String, Result<Input>)> + Send> = if is_tar(&file_path) {
+            Box::new(tar_members(file_path).into_iter().map(move |member| match member {
+                Ok(member) => (format!("{}:{}", filename, member.name), Ok(Input::Member(member))),
+                Err(e) => (filename.clone(), Err(e)),
+            }))
+        } else {
+            Box::new(std::iter::once((filename, Ok(Input::File(file_path)))))
+        };
+        items
+    })
+}
+
+fn process_input(input: Input, name: &str, options: &ExtractOptions, each: &mut dyn FnMut(OutputArticle)) -> Result<()> {
+    match input {
+        Input::File(file_path) => extract_file(&file_path, name, options, each),
+        Input::Member(member) => extract_each_from_bytes(&member.data, name, options, each),
+    }
+}
+
+/// Processes all the files, and the members of the tarballs among them,
+/// in parallel. The articles are passed to handle() in batches of at
+/// most BATCH_SIZE as they are read, with a sequence number in input
+/// order, the file or member name, and whether it is the last batch of
+/// the input. An error ends the input.
+pub fn process_inputs<F>(files: Vec<(PathBuf, String)>, options: &ExtractOptions, handle: &F)
+where
+    F: Fn(usize, &str, Result<Vec<OutputArticle>>, bool) + Sync
+{
+    // Note that the order is unknown.
+    inputs(files).enumerate().par_bridge().for_each(|(seq, (name, input))| {
+        debug!("Starting {}.", name);
+        let mut batch = vec![];
+        let result = input.and_then(|input| process_input(input, &name, options, &mut |article| {
+            batch.push(article);
+            if batch.len() == BATCH_SIZE {
+                handle(seq, &name, Ok(std::mem::take(&mut batch)), false);
+            }
+        }));
+        match result {
+            Ok(()) => handle(seq, &name, Ok(batch), true),
+            Err(e) => {
+                if !batch.is_empty() {
+                    handle(seq, &name, Ok(batch), false);
+                }
+                handle(seq, &name, Err(e), true);
+            },
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_bytes() {
+        let data = r#"{"source": "PubTator", "date": "", "infons": {}, "documents": [
+            {"id": "1001", "infons": {}, "passages": [
+                {"offset": 0, "infons": {"type": "title"}, "text": "A title."},
+                {"offset": 9, "infons": {"type": "abstract"}, "text": "One sentence. Another one."}
+            ]}
+        ]}"#;
+        let options = ExtractOptions { sentences: true, ..Default::default() };
+        let articles = extract_from_bytes(data.as_bytes(), "test", &options).unwrap();
+        assert!(articles.len() == 1);
+        assert!(articles[0].pmid == "1001");
+        let texts: Vec<&str> = articles[0].sentences.iter().map(|s| s.text.as_str()).collect();
+        assert!(texts == ["One sentence.", "Another one."]);
+    }
+
+    #[test]
+    fn splitters() {
+        let data = r#"{"source": "PMC", "date": "", "infons": {}, "documents": [
+            {"id": "1", "infons": {}, "passages": [
+                {"offset": 0, "infons": {"section_type": "RESULTS", "type": "paragraph"},
+                 "text": "The clusters are shown in Suppl. Fig. 4. They were stable."}
+            ]}
+        ]}"#;
+        let sentences = |splitter| {
+            let options = ExtractOptions { sentences: true, splitter, ..Default::default() };
+            let articles = extract_from_bytes(data.as_bytes(), "test", &options).unwrap();
+            articles[0].sentences.iter().map(|s| s.text.clone()).collect::<Vec<_>>()
+        };
+        assert!(sentences(Splitter::English) == ["The clusters are shown in Suppl.", "Fig. 4.", "They were stable."]);
+        assert!(sentences(Splitter::Biomedical) == ["The clusters are shown in Suppl. Fig. 4.", "They were stable."]);
+    }
+
+    #[test]
+    fn headings() {
+        let data = r#"{"source": "PMC", "date": "", "infons": {}, "documents": [
+            {"id": "1", "infons": {}, "passages": [
+                {"offset": 0, "infons": {"section_type": "METHODS", "type": "title_1"}, "text": "Methods"},
+                {"offset": 8, "infons": {"section_type": "METHODS", "type": "title_2"}, "text": "Statistics"},
+                {"offset": 19, "infons": {"section_type": "METHODS", "type": "paragraph"}, "text": "First."},
+                {"offset": 26, "infons": {"section_type": "FIG", "type": "fig_caption"}, "text": "A figure."},
+                {"offset": 36, "infons": {"section_type": "METHODS", "type": "paragraph"}, "text": "Second."},
+                {"offset": 44, "infons": {"section_type": "RESULTS", "type": "paragraph"}, "text": "Third."}
+            ]}
+        ]}"#;
+        let articles = extract_from_bytes(data.as_bytes(), "test", &ExtractOptions::default()).unwrap();
+        let paragraphs = &articles[0].sentences;
+        assert!(paragraphs.len() == 3);
+        assert!(paragraphs[1].headings == ["Methods", "Statistics"]);
+        assert!(paragraphs[1].paragraph == 2);
+        assert!(paragraphs[2].headings.is_empty() && paragraphs[2].paragraph == 1);
+    }
+
+    #[test]
+    fn passage_types() {
+        let data = r#"{"source": "PMC", "date": "", "infons": {}, "documents": [
+            {"id": "1", "infons": {}, "passages": [
+                {"offset": 0, "infons": {"section_type": "ABSTRACT", "type": "abstract_title_1"}, "text": "Background"},
+                {"offset": 11, "infons": {"section_type": "ABSTRACT", "type": "abstract"}, "text": "Why."},
+                {"offset": 16, "infons": {"section_type": "INTRO", "type": "title_1"}, "text": "Introduction"},
+                {"offset": 29, "infons": {"section_type": "INTRO", "type": "paragraph"}, "text": "First."}
+            ]}
+        ]}"#;
+        let texts = |types: &[&str]| {
+            let passage_types = types.iter().map(|t| t.to_string()).collect();
+            let options = ExtractOptions { passage_types, ..Default::default() };
+            let articles = extract_from_bytes(data.as_bytes(), "test", &options).unwrap();
+            articles[0].sentences.iter().map(|s| s.text.clone()).collect::<Vec<_>>()
+        };
+        assert!(texts(&["title"]) == ["Background", "Introduction"]);
+        assert!(texts(&["abstract_title"]) == ["Background"]);
+        assert!(texts(&["abstract"]) == ["Why."]);
+        assert!(texts(&["title_1"]) == ["Introduction"]);
+    }
+
+    #[test]
+    fn offsets() {
+        let data = r#"{"source": "PMC", "date": "", "infons": {}, "documents": [
+            {"id": "1", "infons": {}, "passages": [
+                {"offset": 100, "infons": {"section_type": "INTRO", "type": "paragraph"},
+                 "text": "Cells grew [3]. Then é died (Fig. 2)."}
+            ]}
+        ]}"#;
+        let options = ExtractOptions {
+            sentences: true,
+            cleaner: Cleaner::builtin(&["all"]).unwrap(),
+            ..Default::default()
+        };
+        let articles = extract_from_bytes(data.as_bytes(), "test", &options).unwrap();
+        let sentences = &articles[0].sentences;
+        assert!(sentences[0].text == "Cells grew.");
+        assert!(sentences[0].offset == 100 && sentences[0].length == 15);
+        assert!(sentences[1].text == "Then é died.");
+        assert!(sentences[1].offset == 116 && sentences[1].length == 21);
+    }
+
+    #[test]
+    fn references_and_captions() {
+        let data = r#"{"source": "PMC", "date": "", "infons": {}, "documents": [
+            {"id": "1", "infons": {}, "passages": [
+                {"offset": 0, "infons": {"section_type": "RESULTS", "type": "paragraph"}, "text": "See Figs. 1-2."},
+                {"offset": 15, "infons": {"section_type": "FIG", "type": "fig_caption", "id": "F2"}, "text": "Growth."},
+                {"offset": 23, "infons": {"section_type": "REF", "type": "ref", "pub-id_pmid": "123",
+                 "pub-id_pmc": "456", "name_1": "surname:Doe;given-names:J", "name_0": "surname:Roe;given-names:A",
+                 "source": "Nature", "year": "2001"}, "text": "A paper."}
+            ]}
+        ]}"#;
+        let options = ExtractOptions { references: true, captions: true, ..Default::default() };
+        let articles = extract_from_bytes(data.as_bytes(), "test", &options).unwrap();
+        let article = &articles[0];
+        assert!(article.sentences.len() == 1);
+
+        let reference = &article.references[0];
+        assert!(reference.pmid.as_deref() == Some("123") && reference.pmcid.as_deref() == Some("PMC456"));
+        assert!(reference.authors[0].surname == "Roe" && reference.title == "A paper.");
+
+        let caption = &article.captions[0];
+        assert!(caption.kind == "fig" && caption.id.as_deref() == Some("F2") && caption.label.is_none());
+        assert!(caption.mentions.len() == 1 && caption.mentions[0].offset == 0);
+
+        let articles = extract_from_bytes(data.as_bytes(), "test", &ExtractOptions::default()).unwrap();
+        assert!(articles[0].references.is_empty() && articles[0].captions.is_empty());
+    }
+
+    #[test]
+    fn bioc_sentences() {
+        let data = r#"{"source": "PMC", "date": "", "infons": {}, "documents": [
+            {"id": "1", "infons": {}, "passages": [
+                {"offset": 10, "infons": {"section_type": "INTRO", "type": "paragraph"},
+                 "text": "Grown in E. coli cells. Then no more.",
+                 "sentences": [{"offset": 10, "infons": {}, "text": "Grown in E."}, {"offset": 22, "infons": {}, "text": ""}]},
+                {"offset": 48, "infons": {"section_type": "INTRO", "type": "paragraph"}, "text": "One. Two."}
+            ]}
+        ]}"#;
+        let options = ExtractOptions { sentences: true, bioc_sentences: true, ..Default::default() };
+        let articles = extract_from_bytes(data.as_bytes(), "test", &options).unwrap();
+        let sentences = &articles[0].sentences;
+        let texts: Vec<&str> = sentences.iter().map(|s| s.text.as_str()).collect();
+        assert!(texts == ["Grown in E.", "coli cells. Then no more.", "One.", "Two."]);
+        assert!(sentences[1].offset == 22 && sentences[1].length == 25);
+        assert!(sentences[1].segmentation.as_deref() == Some("bioc"));
+        assert!(sentences[2].segmentation.as_deref() == Some("cutters"));
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use log::{debug, error};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use walkdir::WalkDir;
+
+use anyhow::Result;
+
+// ===========================================================================
+
+// The extensions we read when no include patterns are given.
+const INPUT_EXTENSIONS: [&str; 6] = ["xml", "nxml", "json", "gz", "tar", "tgz"];
+
+/// Which files to take from a directory. The include and exclude
+/// patterns are globs matched against the path below the directory.
+#[derive(Debug, Default, Clone)]
+pub struct FileOptions {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub follow_symlinks: bool,
+    /// Stop after this many files, 0 or None for all of them.
+    pub maxfiles: Option<usize>,
+}
+
+/// The files in the directory tree that the options select. The tree is
+/// walked in sorted order, so maxfiles picks the same files on every run.
+pub fn get_files_in_directory<P: AsRef<Path>>(path: P, options: &FileOptions) -> Result<Vec<PathBuf>> {
+    let mut file_paths = Vec::new();
+
+    let mut counter: usize = options.maxfiles.unwrap_or(0);
+
+    let include = build_globset(&options.include)?;
+    let exclude = build_globset(&options.exclude)?;
+
+    let walker = WalkDir::new(&path)
+        .follow_links(options.follow_symlinks)
+        .sort_by_file_name();
+
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                error!("Skipping {}", e);
+                continue;
+            }
+        };
+        // Without follow_symlinks, links are neither files nor directories.
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(&path).unwrap_or(entry.path());
+        let included = if options.include.is_empty() {
+            relative.extension().is_some_and(|ext| INPUT_EXTENSIONS.iter().any(|e| ext == *e))
+        } else {
+            include.is_match(relative)
+        };
+
+        if included && !exclude.is_match(relative) {
+            debug!("Added {:?} to file list.", entry.path());
+            file_paths.push(entry.into_path());
+
+            if counter > 0 {
+                counter -= 1;
+                if counter == 0 {
+                    debug!("Reached file limit.");
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(file_paths)
+}
+
+/// Reads a list of paths or PMCIDs, from a file or from standard input
+/// with "-", and returns the files found and the entries that could not
+/// be found. PMCIDs and relative paths are looked up in the directory.
+pub fn get_files_from_list(file_list: &str, dirname: Option<&str>, options: &FileOptions) -> Result<(Vec<PathBuf>, Vec<String>)> {
+    let lines: Vec<String> = if file_list == "-" {
+        io::stdin().lock().lines().collect::<Result<_, _>>()?
+    } else {
+        BufReader::new(File::open(file_list)?).lines().collect::<Result<_, _>>()?
+    };
+
+    let mut file_paths = Vec::new();
+    let mut missing = Vec::new();
+
+    // Index of the files in the directory by PMCID, only built when we
+    // need to look one up.
+    let mut pmcids: Option<HashMap<u64, PathBuf>> = None;
+
+    for line in &lines {
+        let entry = line.trim();
+        if entry.is_empty() || entry.starts_with('#') {
+            continue;
+        }
+
+        let path = Path::new(entry);
+        if path.is_file() {
+            file_paths.push(path.to_path_buf());
+            continue;
+        }
+
+        if let Some(dirname) = dirname {
+            let path = Path::new(dirname).join(entry);
+            if path.is_file() {
+                file_paths.push(path);
+                continue;
+            }
+            if let Some(pmcid) = parse_pmcid(entry) {
+                if pmcids.is_none() {
+                    let all = FileOptions { maxfiles: None, ..options.clone() };
+                    let index = get_files_in_directory(dirname, &all)?
+                        .into_iter()
+                        .filter_map(|path| Some((file_pmcid(&path)?, path)))
+                        .collect();
+                    pmcids = Some(index);
+                }
+                if let Some(path) = pmcids.as_ref().unwrap().get(&pmcid) {
+                    file_paths.push(path.clone());
+                    continue;
+                }
+            }
+        }
+
+        missing.push(entry.to_string());
+    }
+
+    if let Some(maxfiles) = options.maxfiles {
+        if maxfiles > 0 {
+            file_paths.truncate(maxfiles);
+        }
+    }
+
+    Ok((file_paths, missing))
+}
+
+// "PMC0001234", "pmc1234" and "1234" are the same article.
+fn parse_pmcid(entry: &str) -> Option<u64> {
+    let digits = entry.strip_prefix("PMC")
+        .or_else(|| entry.strip_prefix("pmc"))
+        .unwrap_or(entry);
+    digits.parse().ok()
+}
+
+// The PMCID from file names like PMC0001234.xml.json.
+fn file_pmcid(path: &Path) -> Option<u64> {
+    let name = path.file_name()?.to_str()?;
+    let stem = name.split('.').next()?;
+    if stem.starts_with("PMC") {
+        parse_pmcid(stem)
+    } else {
+        None
+    }
+}
+
+fn build_globset(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    // A small tree: two files at the top, a link to one of them, a file
+    // we don't read, and a directory with two more.
+    fn tree(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pubmed-rs-{}-{}", name, std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        for file in ["b.xml", "a.json", "notes.txt", "sub/PMC0001234.xml.json", "sub/skip.xml"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("a.json"), dir.join("link.xml")).unwrap();
+        dir
+    }
+
+    fn names(dir: &Path, files: &[PathBuf]) -> Vec<String> {
+        files.iter().map(|f| f.strip_prefix(dir).unwrap().to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn walk() {
+        let dir = tree("walk");
+        let files = |options: &FileOptions| names(&dir, &get_files_in_directory(&dir, options).unwrap());
+
+        // Sorted, without the link or the text file.
+        assert!(files(&FileOptions::default()) == ["a.json", "b.xml", "sub/PMC0001234.xml.json", "sub/skip.xml"]);
+        assert!(files(&FileOptions { maxfiles: Some(2), ..Default::default() }) == ["a.json", "b.xml"]);
+        #[cfg(unix)]
+        assert!(files(&FileOptions { follow_symlinks: true, ..Default::default() }).contains(&"link.xml".to_string()));
+
+        let globs = FileOptions {
+            include: vec!["**/*.xml*".to_string()],
+            exclude: vec!["sub/skip*".to_string()],
+            ..Default::default()
+        };
+        assert!(files(&globs) == ["b.xml", "sub/PMC0001234.xml.json"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn list() {
+        let dir = tree("list");
+        let list = dir.join("list.txt");
+        let absolute = dir.join("b.xml");
+        fs::write(&list, format!("# wanted\na.json\n  PMC1234\npmc99\n\n{}\nmissing.xml\n", absolute.display())).unwrap();
+
+        let (files, missing) = get_files_from_list(list.to_str().unwrap(), dir.to_str(), &FileOptions::default()).unwrap();
+        assert!(names(&dir, &files) == ["a.json", "sub/PMC0001234.xml.json", "b.xml"]);
+        assert!(missing == ["pmc99", "missing.xml"]);
+
+        // Without a directory, only the paths that exist are found.
+        let (files, missing) = get_files_from_list(list.to_str().unwrap(), None, &FileOptions::default()).unwrap();
+        assert!(files == [absolute]);
+        assert!(missing.len() == 4);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use log::warn;
+
+use anyhow::{anyhow, Result};
+
+use crate::json::OutputArticle;
+
+// ===========================================================================
+
+/// Selection of articles on their metadata. The filters are checked as
+/// soon as the front matter has been read, so the paragraphs of a
+/// rejected article are never processed. Articles that don't have the
+/// metadata a filter asks for are rejected.
+///
+/// The rejections are counted per filter, the counts are shared by the
+/// clones of the filter, so they can be read after a parallel run.
+#[derive(Debug, Clone, Default)]
+pub struct ArticleFilter {
+    pub year_from: Option<u32>,
+    pub year_to: Option<u32>,
+    /// Part of the license text, like "CC BY" or "CC0", ignoring case.
+    pub licenses: Vec<String>,
+    /// Journal names, ignoring case.
+    pub journals: Vec<String>,
+    /// Article types, like research-article, ignoring case. They match the
+    /// PubMed publication types too, like "Review".
+    pub article_types: Vec<String>,
+    /// Only articles with one of these IDs, and none with these.
+    pub include_ids: Option<IdList>,
+    pub exclude_ids: Option<IdList>,
+    rejected: Arc<Mutex<BTreeMap<String, usize>>>,
+    // The included IDs we have come across.
+    seen: Arc<Mutex<HashSet<String>>>,
+}
+
+/// A list of PMIDs, PMCIDs and DOIs, from a file with one ID per line.
+/// Only the first column counts, so TSV files with the IDs first can be
+/// used. PMCIDs have the PMC prefix, DOIs start with "10.", and plain
+/// numbers are PMIDs. A header line of words, like "pmid", is skipped,
+/// and so are other lines without an ID, with a warning.
+#[derive(Debug, Clone, Default)]
+pub struct IdList {
+    // The IDs as "pmid:123", "pmcid:PMC456" and "doi:10.1/abc", in file
+    // order for reporting.
+    ids: Vec<String>,
+    keys: HashSet<String>,
+}
+
+impl ArticleFilter {
+    pub fn is_empty(&self) -> bool {
+        self.year_from.is_none() && self.year_to.is_none()
+            && self.licenses.is_empty() && self.journals.is_empty() && self.article_types.is_empty()
+            && self.include_ids.is_none() && self.exclude_ids.is_none()
+    }
+
+    /// The names of the filters that are set.
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names = vec![];
+        if self.year_from.is_some() || self.year_to.is_some() {
+            names.push("year");
+        }
+        if !self.licenses.is_empty() {
+            names.push("license");
+        }
+        if !self.journals.is_empty() {
+            names.push("journal");
+        }
+        if !self.article_types.is_empty() {
+            names.push("article-type");
+        }
+        if self.include_ids.is_some() {
+            names.push("include-ids");
+        }
+        if self.exclude_ids.is_some() {
+            names.push("exclude-ids");
+        }
+        names
+    }
+
+    /// The first filter that rejects the article, if any.
+    pub fn rejects(&self, article: &OutputArticle) -> Option<&'static str> {
+        if self.year_from.is_some() || self.year_to.is_some() {
+            let year: Option<u32> = article.year.parse().ok();
+            let in_range = year.is_some_and(|year| {
+                self.year_from.is_none_or(|from| year >= from) && self.year_to.is_none_or(|to| year <= to)
+            });
+            if !in_range {
+                return Some("year");
+            }
+        }
+        if !self.licenses.is_empty() {
+            let license = article.license.as_deref().unwrap_or("").to_lowercase();
+            if !self.licenses.iter().any(|wanted| license.contains(&wanted.to_lowercase())) {
+                return Some("license");
+            }
+        }
+        if !matches(&self.journals, article.journal.as_deref()) {
+            return Some("journal");
+        }
+        let publication_types = article.publication_types.iter().map(|kind| Some(kind.as_str()));
+        let mut article_types = std::iter::once(article.article_type.as_deref()).chain(publication_types);
+        if !self.article_types.is_empty() && !article_types.any(|kind| matches(&self.article_types, kind)) {
+            return Some("article-type");
+        }
+        if let Some(include) = &self.include_ids {
+            if !article_keys(article).iter().any(|key| include.keys.contains(key)) {
+                return Some("include-ids");
+            }
+        }
+        if let Some(exclude) = &self.exclude_ids {
+            if article_keys(article).iter().any(|key| exclude.keys.contains(key)) {
+                return Some("exclude-ids");
+            }
+        }
+        None
+    }
+
+    /// Checks the article, and counts it when it is rejected. An article
+    /// on the include list has been seen, even if another filter rejects it.
+    pub fn accepts(&self, article: &OutputArticle) -> bool {
+        if let Some(include) = &self.include_ids {
+            let found: Vec<String> = article_keys(article).into_iter()
+                .filter(|key| include.keys.contains(key))
+                .collect();
+            if !found.is_empty() {
+                self.seen.lock().unwrap().extend(found);
+            }
+        }
+        match self.rejects(article) {
+            Some(name) => {
+                *self.rejected.lock().unwrap().entry(name.to_string()).or_default() += 1;
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// The number of articles rejected by every filter so far.
+    pub fn rejected(&self) -> BTreeMap<String, usize> {
+        self.rejected.lock().unwrap().clone()
+    }
+
+    /// The IDs on the include list that no article had so far.
+    pub fn unseen(&self) -> Vec<String> {
+        let Some(include) = &self.include_ids else {
+            return vec![];
+        };
+        let seen = self.seen.lock().unwrap();
+        include.ids.iter()
+            .filter(|key| !seen.contains(*key))
+            .map(|key| key.split_once(':').unwrap().1.to_string())
+            .collect()
+    }
+}
+
+impl IdList {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<IdList> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        let mut list = IdList::default();
+        let mut first = true;
+        for (n, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            let Some(id) = line.trim().split(|c: char| c.is_whitespace() || c == ',').next() else {
+                continue;
+            };
+            if id.is_empty() || id.starts_with('#') {
+                continue;
+            }
+            // The first line can be a header, like "pmid\ttitle". A
+            // first ID with a typo is still warned about.
+            let header = first && id.chars().all(|c| c.is_ascii_alphabetic());
+            first = false;
+            let Some(key) = id_key(id) else {
+                if !header {
+                    warn!("{}:{}: skipped {:?}, it is not a PMID, PMCID or DOI", path.display(), n + 1, id);
+                }
+                continue;
+            };
+            if list.keys.insert(key.clone()) {
+                list.ids.push(key);
+            }
+        }
+        Ok(list)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+// The same ID is written in many ways: PMC0001234, pmc1234,
+// https://doi.org/10.1/ABC. DOIs are case-insensitive.
+fn id_key(id: &str) -> Option<String> {
+    let id = id.trim();
+    if let Some(digits) = id.strip_prefix("PMC").or_else(|| id.strip_prefix("pmc")) {
+        let number: u64 = digits.parse().ok()?;
+        return Some(format!("pmcid:PMC{}", number));
+    }
+    let doi = id.trim_start_matches("https://doi.org/").trim_start_matches("doi:");
+    if doi.starts_with("10.") {
+        return Some(format!("doi:{}", doi.to_lowercase()));
+    }
+    let number: u64 = id.parse().ok()?;
+    Some(format!("pmid:{}", number))
+}
+
+fn article_keys(article: &OutputArticle) -> Vec<String> {
+    let mut keys = vec![];
+    if let Some(key) = id_key(&article.pmid) {
+        keys.push(key);
+    }
+    if let Some(key) = article.pmcid.as_deref().and_then(id_key) {
+        keys.push(key);
+    }
+    if let Some(key) = article.doi.as_deref().and_then(id_key) {
+        keys.push(key);
+    }
+    keys
+}
+
+fn matches(wanted: &[String], value: Option<&str>) -> bool {
+    wanted.is_empty() || value.is_some_and(|value| wanted.iter().any(|w| w.eq_ignore_ascii_case(value)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn filters() {
+        let mut article = OutputArticle::new("1".to_string());
+        article.year = "2016".to_string();
+        article.license = Some("This article is licensed under CC BY 4.0.".to_string());
+        article.article_type = Some("research-article".to_string());
+
+        let filter = ArticleFilter {
+            year_from: Some(2015),
+            year_to: Some(2024),
+            licenses: vec!["cc by".to_string(), "CC0".to_string()],
+            article_types: vec!["Research-Article".to_string()],
+            ..Default::default()
+        };
+        assert!(filter.accepts(&article));
+
+        article.year = "UNK".to_string();
+        assert!(!filter.accepts(&article));
+        article.year = "2016".to_string();
+        article.article_type = Some("editorial".to_string());
+        assert!(!filter.accepts(&article));
+        // A PubMed review is a review-article, and a Review.
+        article.publication_types = vec!["Journal Article".to_string(), "Review".to_string()];
+        article.article_type = Some("review-article".to_string());
+        let reviews = ArticleFilter { article_types: vec!["review".to_string()], ..Default::default() };
+        assert!(reviews.accepts(&article));
+        article.article_type = Some("editorial".to_string());
+        article.publication_types.clear();
+        article.license = None;
+        assert!(!filter.accepts(&article));
+
+        let rejected = filter.clone().rejected();
+        assert!(rejected["year"] == 1 && rejected["article-type"] == 1 && rejected["license"] == 1);
+    }
+
+    #[test]
+    fn ids() {
+        let path = std::env::temp_dir().join(format!("pubmed-rs-ids-{}.tsv", std::process::id()));
+        std::fs::write(&path, "# cohort\npmid\tnote\nPMC0001234\tsome note\n  10.1/ABC\nn/a\n555\n").unwrap();
+        let include = IdList::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(include.len() == 3);
+
+        let filter = ArticleFilter { include_ids: Some(include), ..Default::default() };
+        let mut article = OutputArticle::new("UNK".to_string());
+        article.pmcid = Some("PMC1234".to_string());
+        assert!(filter.accepts(&article));
+
+        let mut other = OutputArticle::new("777".to_string());
+        other.doi = Some("10.1/abc".to_string());
+        assert!(filter.accepts(&other));
+        other.doi = None;
+        assert!(!filter.accepts(&other));
+
+        assert!(filter.unseen() == ["555"]);
+    }
+}
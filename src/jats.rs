@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use crate::json::{Document, Passages, Root};
+use crate::xml::{collapse_whitespace, Element, Node};
+
+// ===========================================================================
+
+/*
+    PMC JATS (.nxml) reader. The article is turned into BioC passages
+    with the same `section_type` and `type` infons the PMC BioC files
+    use, so the section filter and the abbreviation heuristics in
+    extract_json_from_json work unchanged.
+
+    <front>               TITLE/front, ABSTRACT/abstract
+    <body><sec>           INTRO, METHODS, ... from sec-type or title
+    <def-list>            ABBR/paragraph, alternating term and definition
+    <fig>, <table-wrap>   FIG/fig_caption, TABLE/table_caption, TABLE/table
+    <back>                REF/ref, ACK_FUND, APPENDIX, ...
+*/
+
+// Matched in order against the start of the lower-cased sec-type and
+// title, after a section number like "2.1", so "Results and discussion"
+// ends up as RESULTS and "Discussion of methods" as DISCUSS, and "Use
+// case" is not taken for a case report.
+const SECTION_TYPES: &[(&str, &str)] = &[
+    ("intro", "INTRO"),
+    ("background", "INTRO"),
+    ("method", "METHODS"),
+    ("material", "METHODS"),
+    ("patients", "METHODS"),
+    ("experimental", "METHODS"),
+    ("result", "RESULTS"),
+    ("discussion", "DISCUSS"),
+    ("conclusion", "CONCL"),
+    ("case", "CASE"),
+    ("supplementary", "SUPPL"),
+    ("data availability", "SUPPL"),
+    ("abbreviation", "ABBR"),
+    ("acknowledg", "ACK_FUND"),
+    ("funding", "ACK_FUND"),
+    ("author contribution", "AUTH_CONT"),
+    ("competing interest", "COMP_INT"),
+    ("conflict", "COMP_INT"),
+    ("coi statement", "COMP_INT"),
+];
+
+pub fn parse_jats_article(article: &Element) -> Result<Root> {
+    let meta = article.path(&["front", "article-meta"])
+        .ok_or_else(|| anyhow!("JATS <article> has no <front><article-meta>"))?;
+
+    let mut passages = Passages::default();
+
+    let title = meta.path(&["title-group", "article-title"])
+        .map(|t| t.clean_text())
+        .unwrap_or_default();
+    let mut infons = front_infons(meta);
+    if let Some(journal) = article.path(&["front", "journal-meta"]).and_then(journal_title) {
+        infons.insert("source".to_string(), Some(journal));
+    }
+    if let Some(article_type) = article.attr("article-type") {
+        infons.insert("article-type".to_string(), Some(article_type.to_string()));
+    }
+    let id = infons.get("article-id_pmc").cloned().flatten().unwrap_or_default();
+    passages.push("TITLE", "front", title, infons);
+
+    // All keyword groups in one passage, like the BioC files.
+    let keywords: Vec<String> = meta.children_named("kwd-group")
+        .flat_map(|group| group.children_named("kwd"))
+        .map(|kwd| kwd.clean_text())
+        .filter(|kwd| !kwd.is_empty())
+        .collect();
+    passages.push_text("KEYWORD", "keyword", keywords.join("; "));
+
+    for abstr in meta.children_named("abstract") {
+        read_abstract(abstr, 1, &mut passages);
+    }
+    if let Some(body) = article.child("body") {
+        read_body(body, &mut passages);
+    }
+    if let Some(back) = article.child("back") {
+        read_back(back, &mut passages);
+    }
+
+    Ok(Root {
+        source: "PMC".to_string(),
+        date: String::new(),
+        infons: HashMap::new(),
+        documents: vec![Document {
+            id,
+            infons: HashMap::new(),
+            passages: passages.passages,
+            annotations: vec![],
+            relations: vec![],
+        }],
+    })
+}
+
+fn front_infons(meta: &Element) -> HashMap<String, Option<String>> {
+    let mut infons = HashMap::new();
+
+    for id in meta.children_named("article-id") {
+        let value = id.clean_text();
+        match id.attr("pub-id-type") {
+            // The BioC files have the PMCID without the prefix.
+            Some("pmc") | Some("pmcid") => {
+                let value = value.trim_start_matches("PMC").to_string();
+                infons.insert("article-id_pmc".to_string(), Some(value));
+            },
+            Some(kind) => {
+                infons.insert(format!("article-id_{}", kind), Some(value));
+            },
+            None => (),
+        }
+    }
+
+    let year = meta.children_named("pub-date")
+        .find_map(|date| date.child("year"))
+        .map(|year| year.clean_text());
+    if let Some(year) = year {
+        infons.insert("year".to_string(), Some(year));
+    }
+
+    for key in ["volume", "fpage", "lpage"] {
+        if let Some(e) = meta.child(key) {
+            infons.insert(key.to_string(), Some(e.clean_text()));
+        }
+    }
+    if let Some(license) = meta.path(&["permissions", "license"]) {
+        infons.insert("license".to_string(), Some(license.clean_text()));
+    }
+
+    // The authors, not the editors.
+    let authors = meta.children_named("contrib-group")
+        .flat_map(|group| group.children_named("contrib"))
+        .filter(|contrib| contrib.attr("contrib-type").unwrap_or("author") == "author")
+        .filter_map(|contrib| contrib.child("name"));
+    for (n, name) in authors.enumerate() {
+        let surname = name.child("surname").map(|e| e.clean_text()).unwrap_or_default();
+        let given = name.child("given-names").map(|e| e.clean_text()).unwrap_or_default();
+        infons.insert(format!("name_{}", n), Some(format!("surname:{};given-names:{}", surname, given)));
+    }
+
+    infons
+}
+
+// <journal-title> is inside <journal-title-group> in newer files.
+fn journal_title(journal_meta: &Element) -> Option<String> {
+    journal_meta.path(&["journal-title-group", "journal-title"])
+        .or_else(|| journal_meta.child("journal-title"))
+        .map(|title| title.clean_text())
+}
+
+fn read_abstract(abstr: &Element, depth: usize, out: &mut Passages) {
+    for e in abstr.elements() {
+        match e.name.as_str() {
+            "title" => out.push_text("ABSTRACT", &format!("abstract_title_{}", depth), e.clean_text()),
+            "p" => read_paragraph(e, "ABSTRACT", "abstract", out),
+            "sec" => read_abstract(e, depth + 1, out),
+            _ => (),
+        }
+    }
+}
+
+fn read_body(body: &Element, out: &mut Passages) {
+    // Paragraphs outside a section, and sections we cannot classify,
+    // get the type of whatever came before them.
+    let mut current = "INTRO";
+    for e in body.elements() {
+        if e.name == "sec" {
+            if let Some(section_type) = classify_section(e) {
+                current = section_type;
+            }
+            read_section(e, current, 1, out);
+        } else {
+            read_element(e, current, 1, out);
+        }
+    }
+}
+
+fn read_back(back: &Element, out: &mut Passages) {
+    for e in back.elements() {
+        match e.name.as_str() {
+            "ack" => read_section(e, "ACK_FUND", 1, out),
+            "ref-list" => read_refs(e, out),
+            "glossary" => read_section(e, "ABBR", 1, out),
+            "app-group" | "app" => read_section(e, "APPENDIX", 1, out),
+            "fn-group" => read_footnotes(e, out),
+            "sec" | "notes" => {
+                let section_type = classify_section(e).unwrap_or("SUPPL");
+                read_section(e, section_type, 1, out);
+            },
+            _ => (),
+        }
+    }
+}
+
+// Subsections keep the type of the section they are in.
+fn read_section(sec: &Element, section_type: &str, depth: usize, out: &mut Passages) {
+    for e in sec.elements() {
+        read_element(e, section_type, depth, out);
+    }
+}
+
+fn read_element(e: &Element, section_type: &str, depth: usize, out: &mut Passages) {
+    match e.name.as_str() {
+        "title" => out.push_text(section_type, &format!("title_{}", depth), e.clean_text()),
+        "p" => read_paragraph(e, section_type, "paragraph", out),
+        "list" => out.push_text(section_type, "paragraph", e.clean_text()),
+        "sec" | "app" => read_section(e, section_type, depth + 1, out),
+        "boxed-text" => read_section(e, section_type, depth, out),
+        "def-list" => read_def_list(e, out),
+        "fig" => read_fig(e, out),
+        "table-wrap" => read_table(e, out),
+        _ => (),
+    }
+}
+
+fn classify_section(sec: &Element) -> Option<&'static str> {
+    let sec_type = sec.attr("sec-type").unwrap_or_default().to_lowercase().replace('-', " ");
+    let title = sec.child("title").map(|t| t.clean_text().to_lowercase()).unwrap_or_default();
+
+    [sec_type, title].iter().find_map(|label| {
+        let label = label.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c.is_whitespace());
+        SECTION_TYPES.iter()
+            .find(|(key, _)| label.starts_with(key))
+            .map(|(_, section_type)| *section_type)
+    })
+}
+
+// Figures and tables can sit inside a paragraph. They are taken out of
+// the paragraph text and added as passages of their own after it.
+fn read_paragraph(p: &Element, section_type: &str, par_type: &str, out: &mut Passages) {
+    let mut text = String::new();
+    let mut floats = vec![];
+    collect_paragraph(p, &mut text, &mut floats);
+
+    out.push_text(section_type, par_type, collapse_whitespace(&text));
+    for float in floats {
+        read_element(float, section_type, 1, out);
+    }
+}
+
+fn collect_paragraph<'a>(e: &'a Element, text: &mut String, floats: &mut Vec<&'a Element>) {
+    for c in &e.children {
+        match c {
+            Node::Text(t) => text.push_str(t),
+            Node::Element(c) if c.name == "fig" || c.name == "table-wrap" => floats.push(c),
+            Node::Element(c) => collect_paragraph(c, text, floats),
+        }
+    }
+}
+
+// The abbreviation heuristics expect alternating abbreviation and
+// definition paragraphs, so both are always added, even when empty.
+fn read_def_list(list: &Element, out: &mut Passages) {
+    for e in list.elements() {
+        match e.name.as_str() {
+            "title" => out.push_text("ABBR", "title_1", e.clean_text()),
+            "def-item" => {
+                let term = e.child("term").map(|t| t.clean_text()).unwrap_or_default();
+                let def = e.child("def").map(|d| d.clean_text()).unwrap_or_default();
+                out.push("ABBR", "paragraph", term, HashMap::new());
+                out.push("ABBR", "paragraph", def, HashMap::new());
+            },
+            "def-list" => read_def_list(e, out),
+            _ => (),
+        }
+    }
+}
+
+fn read_fig(fig: &Element, out: &mut Passages) {
+    let caption = fig.child("caption").map(|c| c.clean_text()).unwrap_or_default();
+    out.push("FIG", "fig_caption", caption, id_infons(fig));
+}
+
+fn read_table(wrap: &Element, out: &mut Passages) {
+    let caption = wrap.child("caption").map(|c| c.clean_text()).unwrap_or_default();
+    out.push("TABLE", "table_caption", caption, id_infons(wrap));
+
+    // The markup goes along, for the rows and columns.
+    if let Some(table) = wrap.child("table") {
+        let mut infons = id_infons(wrap);
+        infons.insert("xml".to_string(), Some(table.to_xml()));
+        out.push("TABLE", "table", table.clean_text(), infons);
+    }
+    if let Some(foot) = wrap.child("table-wrap-foot") {
+        out.push_text("TABLE", "table_footnote", foot.clean_text());
+    }
+}
+
+fn id_infons(e: &Element) -> HashMap<String, Option<String>> {
+    let mut infons = HashMap::new();
+    if let Some(id) = e.attr("id") {
+        infons.insert("id".to_string(), Some(id.to_string()));
+    }
+    if let Some(label) = e.child("label") {
+        infons.insert("label".to_string(), Some(label.clean_text()));
+    }
+    infons
+}
+
+fn read_footnotes(group: &Element, out: &mut Passages) {
+    for fnote in group.children_named("fn") {
+        let section_type = match fnote.attr("fn-type") {
+            Some("conflict") | Some("COI-statement") => "COMP_INT",
+            Some("financial-disclosure") => "ACK_FUND",
+            Some("con") => "AUTH_CONT",
+            _ => "SUPPL",
+        };
+        out.push_text(section_type, "footnote", fnote.clean_text());
+    }
+}
+
+fn read_refs(list: &Element, out: &mut Passages) {
+    for e in list.elements() {
+        match e.name.as_str() {
+            "title" => out.push_text("REF", "title_1", e.clean_text()),
+            "ref" => read_ref(e, out),
+            "ref-list" => read_refs(e, out),
+            _ => (),
+        }
+    }
+}
+
+// Like BioC, the text of a reference passage is the title of the cited
+// work, with the bibliographic details in the infons.
+fn read_ref(r: &Element, out: &mut Passages) {
+    let mut infons = id_infons(r);
+    let citation = r.elements().find(|e| e.name.ends_with("citation"));
+
+    let text = match citation {
+        Some(citation) => {
+            for pub_id in citation.children_named("pub-id") {
+                if let Some(kind) = pub_id.attr("pub-id-type") {
+                    infons.insert(format!("pub-id_{}", kind), Some(pub_id.clean_text()));
+                }
+            }
+            for field in ["source", "year", "volume", "fpage", "lpage"] {
+                if let Some(e) = citation.child(field) {
+                    infons.insert(field.to_string(), Some(e.clean_text()));
+                }
+            }
+            let mut names = vec![];
+            citation.find_all("name", &mut names);
+            for (n, name) in names.iter().enumerate() {
+                let surname = name.child("surname").map(|s| s.clean_text()).unwrap_or_default();
+                let given = name.child("given-names").map(|g| g.clean_text()).unwrap_or_default();
+                infons.insert(format!("name_{}", n), Some(format!("surname:{};given-names:{}", surname, given)));
+            }
+            citation.child("article-title")
+                .map(|t| t.clean_text())
+                .unwrap_or_else(|| citation.clean_text())
+        },
+        None => r.clean_text(),
+    };
+
+    out.push("REF", "ref", text, infons);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::xml;
+
+    #[test]
+    fn sections() {
+        let data = r#"<article article-type="research-article">
+<front><article-meta>
+<article-id pub-id-type="pmid">37000001</article-id><article-id pub-id-type="pmc">PMC10546722</article-id>
+<title-group><article-title>Cerium oxide and <italic>tendons</italic></article-title></title-group>
+<pub-date pub-type="epub"><year>2023</year></pub-date>
+<abstract><p>Tendon injuries have a high incidence.</p></abstract>
+</article-meta></front>
+<body>
+<sec sec-type="intro"><title>Introduction</title><p>Some introduction.</p></sec>
+<sec><title>Materials and methods</title><sec><title>Statistical analysis</title><p>We used R.</p></sec></sec>
+<sec><title>Use case</title><p>A scenario.</p></sec>
+<sec><title>Abbreviations</title><def-list><def-item><term>ROS</term><def><p>Reactive oxygen species</p></def></def-item></def-list></sec>
+</body>
+<back><ref-list><ref id="r1"><element-citation><article-title>A paper</article-title><pub-id pub-id-type="pmid">123</pub-id></element-citation></ref></ref-list></back>
+</article>"#;
+
+        let root = parse_jats_article(&xml::parse(data).unwrap()).unwrap();
+        let passages = &root.documents[0].passages;
+
+        let types: Vec<(String, String)> = passages.iter()
+            .map(|p| (p.infons["section_type"].clone().unwrap(), p.infons["type"].clone().unwrap()))
+            .collect();
+        let query_types = vec![
+            ("TITLE", "front"),
+            ("ABSTRACT", "abstract"),
+            ("INTRO", "title_1"),
+            ("INTRO", "paragraph"),
+            ("METHODS", "title_1"),
+            ("METHODS", "title_2"),
+            ("METHODS", "paragraph"),
+            ("METHODS", "title_1"),
+            ("METHODS", "paragraph"),
+            ("ABBR", "title_1"),
+            ("ABBR", "paragraph"),
+            ("ABBR", "paragraph"),
+            ("REF", "ref"),
+        ];
+        assert!(types.len() == query_types.len());
+        for ((section_type, par_type), (query_section, query_type)) in types.iter().zip(query_types) {
+            assert!(section_type == query_section && par_type == query_type);
+        }
+
+        assert!(passages[0].text == "Cerium oxide and tendons");
+        assert!(passages[0].infons["article-id_pmc"] == Some("10546722".to_string()));
+        assert!(passages[0].infons["year"] == Some("2023".to_string()));
+        assert!(passages[11].text == "Reactive oxygen species");
+        assert!(passages[12].infons["pub-id_pmid"] == Some("123".to_string()));
+    }
+
+    #[test]
+    fn front() {
+        let data = r#"<article article-type="editorial">
+<front><journal-meta><journal-title-group><journal-title>Sci Rep</journal-title></journal-title-group></journal-meta>
+<article-meta>
+<article-id pub-id-type="doi">10.1/x</article-id>
+<title-group><article-title>A title</article-title></title-group>
+<contrib-group><contrib contrib-type="author"><name><surname>Smith</surname><given-names>John A</given-names></name></contrib>
+<contrib contrib-type="editor"><name><surname>Jones</surname><given-names>Ann</given-names></name></contrib></contrib-group>
+<volume>13</volume><fpage>101</fpage><lpage>110</lpage>
+<permissions><license><license-p>CC BY 4.0</license-p></license></permissions>
+<kwd-group><kwd>Tendon</kwd><kwd>Stem cells</kwd></kwd-group>
+</article-meta></front>
+</article>"#;
+
+        let root = parse_jats_article(&xml::parse(data).unwrap()).unwrap();
+        let passages = &root.documents[0].passages;
+        let infons = &passages[0].infons;
+        assert!(infons["source"] == Some("Sci Rep".to_string()));
+        assert!(infons["article-type"] == Some("editorial".to_string()));
+        assert!(infons["article-id_doi"] == Some("10.1/x".to_string()));
+        assert!(infons["name_0"] == Some("surname:Smith;given-names:John A".to_string()));
+        assert!(!infons.contains_key("name_1"));
+        assert!(infons["fpage"] == Some("101".to_string()));
+        assert!(infons["license"] == Some("CC BY 4.0".to_string()));
+        assert!(passages[1].infons["section_type"] == Some("KEYWORD".to_string()));
+        assert!(passages[1].text == "Tendon; Stem cells");
+    }
+}
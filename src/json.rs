@@ -1,206 +1,933 @@
-use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
 use serde::{Deserialize, Serialize};
 
 use std::path::Path;
 
-use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::BTreeMap;
 
-use regex::Regex;
+use anyhow::{anyhow, Result};
+use log::error;
+use crate::annotations::{anchor, kept_relations, output_relation, passage_spans, OutputAnnotation, OutputRelation};
+use crate::captions::{Caption, CaptionCollector};
+use crate::extract::{ExtractOptions, Splitter};
+use crate::bioc_xml::parse_bioc_collection;
+use crate::jats::parse_jats_article;
+use crate::medline::MedlineReader;
+use crate::tables::Table;
+use crate::xml;
 
-use crate::Args;
-use clap::Parser;
-
-use anyhow::{Result};
-use crate::error;
+use flate2::read::MultiGzDecoder;
 
 // ===========================================================================
 
 #[derive(Debug, Deserialize, Serialize)]
-struct Root {
-    source: String,
-    date: String,
+pub struct Root {
+    pub source: String,
+    pub date: String,
     //key: String,
-    infons: HashMap<String, Option<String>>,
-    documents: Vec<Document>,
+    pub infons: HashMap<String, Option<String>>,
+    pub documents: Vec<Document>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-struct Document {
-    id: String,
-    infons: HashMap<String, Option<String>>,
-    passages: Vec<Passage>,
-    //annotations: Vec<Annotation>,
-    //relations: Vec<Relation>,
+pub struct Document {
+    pub id: String,
+    pub infons: HashMap<String, Option<String>>,
+    pub passages: Vec<Passage>,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+    #[serde(default)]
+    pub relations: Vec<Relation>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Passage {
+    pub offset: u32,
+    pub infons: HashMap<String, Option<String>>,
+    pub text: String,
+    #[serde(default)]
+    pub sentences: Vec<Sentence>,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+    #[serde(default)]
+    pub relations: Vec<Relation>,
 }
 
+/// A sentence from the splitter that made the BioC file. The offset is a
+/// document offset like the passage one, and the text can be left out.
 #[derive(Debug, Deserialize, Serialize)]
-struct Passage {
+pub struct Sentence {
+    pub offset: u32,
+    #[serde(default)]
+    pub infons: HashMap<String, Option<String>>,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+    #[serde(default)]
+    pub relations: Vec<Relation>,
+}
+
+/// An entity mention, like the genes, diseases and chemicals in the
+/// PubTator files. The infons have its type and identifier, and the
+/// locations are document offsets, more than one for discontinuous
+/// mentions.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Annotation {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub infons: HashMap<String, Option<String>>,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub locations: Vec<Location>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub offset: u32,
+    pub length: u32,
+}
+
+/// A relation between annotations, or other relations, by their ids.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Relation {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub infons: HashMap<String, Option<String>>,
+    #[serde(default)]
+    pub nodes: Vec<RelationNode>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RelationNode {
+    pub refid: String,
+    #[serde(default)]
+    pub role: String,
+}
+
+/// Builds passages for the readers of non-BioC formats, with running
+/// offsets one character apart like in BioC.
+#[derive(Default)]
+pub struct Passages {
+    pub passages: Vec<Passage>,
     offset: u32,
-    infons: HashMap<String, Option<String>>,
-    text: String,
-    //sentences: Vec<Sentence>,
-    //annotations: Vec<Annotation>,
-    //relations: Vec<Relation>,
+}
+
+impl Passages {
+    pub fn push(&mut self, section_type: &str, par_type: &str, text: String, mut infons: HashMap<String, Option<String>>) {
+        infons.insert("section_type".to_string(), Some(section_type.to_string()));
+        infons.insert("type".to_string(), Some(par_type.to_string()));
+        let length = text.chars().count() as u32;
+        self.passages.push(Passage {
+            offset: self.offset,
+            infons,
+            text,
+            sentences: vec![],
+            annotations: vec![],
+            relations: vec![],
+        });
+        self.offset += length + 1;
+    }
+
+    /// Empty titles and paragraphs are left out.
+    pub fn push_text(&mut self, section_type: &str, par_type: &str, text: String) {
+        if !text.is_empty() {
+            self.push(section_type, par_type, text, HashMap::new());
+        }
+    }
 }
 
 /*
     Output JSON.
 */
-#[derive(Deserialize, Serialize, Debug)]
+
+/// A paragraph, or a sentence when splitting, with the section it is in.
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct OutputParagraph {
     pub r#type: String,
+    /// The BioC passage type, like paragraph, title_1 or fig_caption.
+    pub passage_type: String,
     pub text: String,
+    /// The section headings the paragraph is under, outermost first,
+    /// like ["Methods", "Statistical analysis"].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headings: Vec<String>,
+    /// Number of the paragraph under the last heading, from 1. Titles,
+    /// figures and tables are 0, and the sentences of a paragraph share
+    /// its number.
+    #[serde(default)]
+    pub paragraph: usize,
+    /// Position in the document, in characters, as the BioC offsets.
+    /// With cleaning, the span covers the text the paragraph or sentence
+    /// came from, removed parts included.
+    #[serde(default)]
+    pub offset: u32,
+    #[serde(default)]
+    pub length: u32,
+    /// The entity mentions in the text, from the BioC annotations.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<OutputAnnotation>,
+    /// Where the sentence boundaries came from: "bioc" for the sentences
+    /// in the file, "cutters" for our own splitter. Not set on paragraphs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segmentation: Option<String>,
 }
 
+/// An article with its metadata and the paragraphs or sentences we took
+/// from it.
 #[derive(Deserialize, Serialize, Debug)]
 pub struct OutputArticle {
     pub sentences: Vec<OutputParagraph>,
     pub abbreviations: HashMap<String, String>,
     pub year: String,
+    /// The PubMed ID, UNK for PMC articles without one.
     pub pmid: String,
+    /// The PMC ID with its prefix, like PMC10546722.
+    #[serde(default)]
+    pub pmcid: Option<String>,
+    pub title: String,
+    #[serde(default)]
+    pub doi: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<Author>,
+    #[serde(default)]
+    pub journal: Option<String>,
+    #[serde(default)]
+    pub volume: Option<String>,
+    #[serde(default)]
+    pub fpage: Option<String>,
+    #[serde(default)]
+    pub lpage: Option<String>,
+    #[serde(default)]
+    pub license: Option<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub article_type: Option<String>,
+    /// The PubMed publication types, like "Journal Article" and "Review".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub publication_types: Vec<String>,
+    /// The BioC relations between the annotations of the paragraphs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relations: Vec<OutputRelation>,
+    /// The works cited, with ExtractOptions::references.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub references: Vec<Reference>,
+    /// Figure and table captions, with ExtractOptions::captions.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub captions: Vec<Caption>,
+    /// The tables with their rows and columns, with ExtractOptions::tables.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tables: Vec<Table>,
+    /// Characters removed by each cleaning rule.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub cleaned: BTreeMap<String, usize>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Author {
+    pub surname: String,
+    pub given_names: String,
+}
+
+/// A cited work, from the infons of a ref passage. The title is the
+/// passage text, which can be empty for books and web pages.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Reference {
+    #[serde(default)]
+    pub id: Option<String>,
     pub title: String,
+    #[serde(default)]
+    pub pmid: Option<String>,
+    #[serde(default)]
+    pub pmcid: Option<String>,
+    #[serde(default)]
+    pub doi: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<Author>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub year: Option<String>,
+    #[serde(default)]
+    pub volume: Option<String>,
+    #[serde(default)]
+    pub fpage: Option<String>,
+    #[serde(default)]
+    pub lpage: Option<String>,
 }
 
-// Output OutputArticle and OutputChunk with the same function.
+impl OutputArticle {
+    pub fn new(pmid: String) -> Self {
+        OutputArticle {
+            sentences: vec![],
+            abbreviations: HashMap::new(),
+            year: "UNK".to_string(),
+            pmid,
+            pmcid: None,
+            title: "UNK".to_string(),
+            doi: None,
+            authors: vec![],
+            journal: None,
+            volume: None,
+            fpage: None,
+            lpage: None,
+            license: None,
+            keywords: vec![],
+            article_type: None,
+            publication_types: vec![],
+            relations: vec![],
+            references: vec![],
+            captions: vec![],
+            tables: vec![],
+            cleaned: BTreeMap::new(),
+        }
+    }
+
+    /// The ID the articles are sorted and indexed on: the PMCID for PMC
+    /// articles, the PMID for the others.
+    pub fn id(&self) -> &str {
+        self.pmcid.as_deref().unwrap_or(&self.pmid)
+    }
+}
+
+/// Output OutputArticle and OutputChunk with the same function.
 #[derive(Deserialize, Serialize, Debug)]
 pub struct OutputChunk {
     pub articles: BTreeMap<String, OutputArticle>,
 }
 
+/// Output records, as pretty JSON or as JSONL lines.
 pub trait OutputData: Serialize {
     fn to_json(&self) -> String {
         serde_json::to_string_pretty(self).unwrap()
     }
+
+    // One line, for JSONL output.
+    fn to_json_line(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
 }
 impl OutputData for OutputArticle {}
 impl OutputData for OutputChunk {}
 
+/// The references of an article without its text, one record per
+/// article for --references. Articles without references have an
+/// empty list.
+#[derive(Serialize, Debug)]
+pub struct ReferenceList<'a> {
+    pub pmid: &'a str,
+    pub pmcid: Option<&'a str>,
+    pub doi: Option<&'a str>,
+    pub references: &'a [Reference],
+}
+
+/// The same for the captions, for --captions.
+#[derive(Serialize, Debug)]
+pub struct CaptionList<'a> {
+    pub pmid: &'a str,
+    pub pmcid: Option<&'a str>,
+    pub doi: Option<&'a str>,
+    pub captions: &'a [Caption],
+}
+
+/// One table with the IDs of its article, for --tables.
+#[derive(Serialize, Debug)]
+pub struct TableRecord<'a> {
+    pub pmid: &'a str,
+    pub pmcid: Option<&'a str>,
+    pub doi: Option<&'a str>,
+    #[serde(flatten)]
+    pub table: &'a Table,
+}
+
+impl OutputData for ReferenceList<'_> {}
+impl OutputData for CaptionList<'_> {}
+impl OutputData for TableRecord<'_> {}
+
+impl OutputArticle {
+    pub fn reference_list(&self) -> ReferenceList<'_> {
+        ReferenceList {
+            pmid: &self.pmid,
+            pmcid: self.pmcid.as_deref(),
+            doi: self.doi.as_deref(),
+            references: &self.references,
+        }
+    }
+
+    pub fn caption_list(&self) -> CaptionList<'_> {
+        CaptionList {
+            pmid: &self.pmid,
+            pmcid: self.pmcid.as_deref(),
+            doi: self.doi.as_deref(),
+            captions: &self.captions,
+        }
+    }
+
+    pub fn table_records(&self) -> impl Iterator<Item = TableRecord<'_>> {
+        self.tables.iter().map(|table| TableRecord {
+            pmid: &self.pmid,
+            pmcid: self.pmcid.as_deref(),
+            doi: self.doi.as_deref(),
+            table,
+        })
+    }
+}
+
 pub fn output_json<T: OutputData>(data: &T) {
     println!("{}", data.to_json());
 }
 
-// The extra filename is for printing error info. Our signature doesn't
-// allow printing of "Path", and the directory version sends Paths
-// this way. This should be fixed!
-pub fn extract_json_from_json<P: AsRef<Path>>(file_path: P, filename: &str, allowed: &BTreeSet<String>) -> Result<OutputArticle> {
-    let data = fs::read_to_string(file_path)?;
-    
-    //let json: Value = serde_json::from_str(&data)?;
-    let root: Root = serde_json::from_str(&data)? ;//.expect("JSON was not well-formatted");
+// BioC comes as JSON or as XML, and PMC full text also as JATS XML.
+// We look at the content rather than the extension, the files are
+// called .xml.json, .xml, .nxml or .json.
+fn parse_root(data: &str) -> Result<Root> {
+    let data = data.trim_start_matches('\u{feff}').trim_start();
+    if data.starts_with('<') {
+        let element = xml::parse(data)?;
+        match element.name.as_str() {
+            "collection" => parse_bioc_collection(&element),
+            "article" => parse_jats_article(&element),
+            other => Err(anyhow!("unknown XML document type <{}>", other)),
+        }
+    } else {
+        //let json: Value = serde_json::from_str(&data)?;
+        let root: Root = serde_json::from_str(data)?; //.expect("JSON was not well-formatted");
+        Ok(root)
+    }
+}
+
+/// The extra filename is for printing error info, it is the file or
+/// archive member name the caller knows it by.
+pub fn extract_json_from_json<P: AsRef<Path>>(file_path: P, filename: &str, options: &ExtractOptions) -> Result<Vec<OutputArticle>> {
+    extract_json_from_reader(File::open(file_path)?, filename, options)
+}
+
+/// The same from a reader, for archive members. Gzipped data is
+/// decompressed on the fly.
+pub fn extract_json_from_reader<R: Read>(reader: R, filename: &str, options: &ExtractOptions) -> Result<Vec<OutputArticle>> {
+    let mut data = String::new();
+    open_reader(reader)?.read_to_string(&mut data)?;
+    let root = parse_root(&data)?;
     //dbg!("{:?}", &root);
 
+    Ok(extract_root(root, filename, options))
+}
+
+/// PubMed baseline files are read one citation at a time, every
+/// citation becomes an article of its own, which is passed to each()
+/// straight away. A citation we cannot read is logged and skipped.
+pub fn extract_medline<P: AsRef<Path>>(file_path: P, filename: &str, options: &ExtractOptions,
+                                       each: &mut dyn FnMut(OutputArticle)) -> Result<()> {
+    extract_medline_from_reader(File::open(file_path)?, filename, options, each)
+}
+
+pub fn extract_medline_from_reader<R: Read>(reader: R, filename: &str, options: &ExtractOptions,
+                                            each: &mut dyn FnMut(OutputArticle)) -> Result<()> {
+    for document in MedlineReader::new(open_reader(reader)?) {
+        match document {
+            Ok(document) => {
+                if let Some(article) = extract_document(document, filename, options) {
+                    each(article);
+                }
+            },
+            Err(e) => error!("{}: skipped a citation: {}", filename, e),
+        }
+    }
+    Ok(())
+}
+
+/// Looks at the start of the (possibly gzipped) file for the root
+/// element of the PubMed baseline and update files.
+pub fn is_medline<P: AsRef<Path>>(file_path: P) -> Result<bool> {
+    is_medline_reader(File::open(file_path)?)
+}
+
+pub fn is_medline_reader<R: Read>(reader: R) -> Result<bool> {
+    let mut head = Vec::new();
+    open_reader(reader)?.take(4096).read_to_end(&mut head)?;
+    Ok(String::from_utf8_lossy(&head).contains("<PubmedArticleSet"))
+}
+
+// Wraps a reader, decompressing it on the fly if it is gzipped.
+fn open_reader<'a, R: Read + 'a>(reader: R) -> Result<Box<dyn BufRead + 'a>> {
+    let mut reader = BufReader::new(reader);
+    if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+// Every document in the collection becomes an article of its own. The
+// PMC files have one document per file, but the PubTator and BioC API
+// collections bundle hundreds.
+fn extract_root(root: Root, filename: &str, options: &ExtractOptions) -> Vec<OutputArticle> {
+    root.documents.into_iter()
+        .filter_map(|document| extract_document(document, filename, options))
+        .collect()
+}
+
+// None when the article is rejected by the filter.
+fn extract_document(document: Document, filename: &str, options: &ExtractOptions) -> Option<OutputArticle> {
+    // Documents without a front passage, like the PubTator ones, are
+    // known by their document id.
+    let pmid = if document.id.is_empty() { "UNK".to_string() } else { document.id.clone() };
+
     // Test output JSON
-    let mut od = OutputArticle { // OutputDocument?
-        sentences: vec![],
-        abbreviations: HashMap::new(),
-        year: "UNK".to_string(),
-        pmid: "UNK".to_string(),
-        title: "UNK".to_string(),
-    };
-
-    let args = Args::parse();
-    
-    for document in root.documents {
-        //println!("{}", document.id);
-        
-        let mut abbr: Option<String> = None;
-
-        for passage in document.passages {
-            //dbg!("{:?}", &passage);
-
-            // Some documents don't have section types?
-            if passage.infons.contains_key("section_type") {
-                
-                let section_type = &passage.infons["section_type"].clone().unwrap(); // clone().unwrap because Option<...>                
-                let par_type = &passage.infons["type"].clone().unwrap();  // because Option<...>
-                
-                if par_type == "front" && passage.offset == 0 {
-                    if passage.infons.contains_key("year") {
-                        od.year = passage.infons["year"].clone().unwrap();
-                     }  
-                    if passage.infons.contains_key("article-id_pmc") {
-                        od.pmid = passage.infons["article-id_pmc"].clone().unwrap();
-                    }
-                    od.title = passage.text.clone();
+    let mut od = OutputArticle::new(pmid); // OutputDocument?
+
+    let mut abbr: Option<String> = None;
+
+    // Whether the filter has seen the front matter.
+    let mut filtered = false;
+
+    // The heading path, the section it belongs to, and the number of
+    // paragraphs under the last heading.
+    let mut headings: Vec<String> = vec![];
+    let mut heading_section = String::new();
+    let mut paragraph: usize = 0;
+
+    let mut captions = CaptionCollector::default();
+
+    // The id and text of the last table caption, for the table after it.
+    let mut table_caption: Option<(Option<String>, String)> = None;
+
+    // Annotations can be on the document as well as on the passages.
+    let document_annotations = document.annotations;
+    od.relations.extend(document.relations.iter().map(output_relation));
+
+    for mut passage in document.passages {
+        //dbg!("{:?}", &passage);
+        od.relations.extend(passage.relations.iter().map(output_relation));
+        for sentence in &passage.sentences {
+            od.relations.extend(sentence.relations.iter().map(output_relation));
+        }
+
+        // The PubTator collections only have a type on their title and
+        // abstract passages, which we map onto the PMC ones.
+        if !passage.infons.contains_key("section_type") {
+            let par_type = passage.infons.get("type").cloned().flatten();
+            let types = match par_type.as_deref() {
+                Some("title") => Some(("TITLE", "front")),
+                Some("abstract") => Some(("ABSTRACT", "abstract")),
+                _ => None,
+            };
+            if let Some((section_type, par_type)) = types {
+                passage.infons.insert("section_type".to_string(), Some(section_type.to_string()));
+                passage.infons.insert("type".to_string(), Some(par_type.to_string()));
+            }
+        }
+
+        // Some documents don't have section types?
+        if passage.infons.contains_key("section_type") {
+            
+            let section_type = &passage.infons["section_type"].clone().unwrap(); // clone().unwrap because Option<...>                
+            let par_type = &passage.infons["type"].clone().unwrap();  // because Option<...>
+            
+            if par_type == "front" && passage.offset == 0 {
+                read_front(&mut od, &passage);
+                if !filtered && !options.filter.accepts(&od) {
+                    return None;
                 }
+                filtered = true;
+            }
+
+            // The keywords are a section of their own, the keywords
+            // separated by commas or semicolons.
+            if section_type == "KEYWORD" {
+                let separator = if passage.text.contains(';') { ';' } else { ',' };
+                od.keywords.extend(passage.text.split(separator)
+                                   .map(|keyword| keyword.trim().to_string())
+                                   .filter(|keyword| !keyword.is_empty()));
+            }
+
+            // A new section starts without headings, and a title replaces
+            // the ones at its level and below. Paragraphs in a section
+            // without a title of its own have no heading. Figures and
+            // tables float between the paragraphs, they are under the
+            // current headings but don't change them.
+            let floating = section_type == "FIG" || section_type == "TABLE";
+            let level = heading_level(par_type);
+            if !floating {
+                if *section_type != heading_section {
+                    headings.clear();
+                    heading_section = section_type.clone();
+                    paragraph = 0;
+                }
+                if let Some(level) = level {
+                    headings.truncate(level - 1);
+                    headings.push(passage.text.clone());
+                    paragraph = 0;
+                } else {
+                    paragraph += 1;
+                }
+            }
+
+            // References and captions are taken whatever the section
+            // policy, which drops them by default.
+            if options.references && par_type == "ref" {
+                od.references.push(read_reference(&passage));
+            }
+            if options.captions {
+                let kind = match par_type.as_str() {
+                    "fig_caption" | "fig_title_caption" => Some("fig"),
+                    "table_caption" | "table_title_caption" => Some("table"),
+                    _ => None,
+                };
+                let infon = |key: &str| passage.infons.get(key).cloned().flatten().filter(|v| !v.is_empty());
+                if let Some(kind) = kind {
+                    captions.add_caption(kind, infon("id"), infon("label"), &passage.text, passage.offset);
+                } else if par_type == "paragraph" && !floating {
+                    captions.add_paragraph(OutputParagraph {
+                        r#type: section_type.to_string(),
+                        passage_type: par_type.to_string(),
+                        text: passage.text.clone(),
+                        headings: headings.clone(),
+                        paragraph,
+                        offset: passage.offset,
+                        length: passage.text.chars().count() as u32,
+                        annotations: vec![],
+                        segmentation: None,
+                    });
+                }
+            }
 
-                if allowed.is_empty() {
-                    if (section_type == "REF")
-                        || (section_type == "FIG")
-                        || (section_type == "TABLE")
-                        || (section_type == "APPENDIX")
-                        || (section_type == "COMP_INT")
-                        || (section_type == "CASE")
-                        //|| (section_type == "METHODS") // Yes, no, maybe?
-                        || (section_type == "AUTH_CONT")
-                        || (section_type == "ACK_FUND")
-                        || (section_type == "SUPPL")
-                        || (section_type == "REVIEW_INFO") {
-                            continue;
-                        }
-                } else { // allowed is not empty
-                    if section_type != "ABBR" { // but ABBR goes through anyway
-                        if ! allowed.contains(section_type) {
-                            continue;
-                        }
+            if options.tables {
+                read_table(&mut od, &passage, par_type, &mut table_caption, filename);
+            }
+
+            if !options.sections.allows(section_type) {
+                continue;
+            }
+
+            // Alternating abbreviation-meaning.
+            if (section_type == "ABBR") && (par_type == "paragraph") {
+                if abbr.is_none() { 
+                    //println!("ABBR {}\t", passage.text);
+                    if passage.text.len() < 10 {
+                        //if !passage.text.contains(char::is_whitespace) {
+                        abbr = Some(passage.text);
                     }
+                } else {
+                    //println!("{}", passage.text);
+                    od.abbreviations.insert(abbr.clone().unwrap(), passage.text);
+                    abbr = None;
                 }
-                
-                // Alternating abbreviation-meaning.
-                if (section_type == "ABBR") && (par_type == "paragraph") {
-                    if abbr.is_none() { 
-                        //println!("ABBR {}\t", passage.text);
-                        if passage.text.len() < 10 {
-                            //if !passage.text.contains(char::is_whitespace) {
-                            abbr = Some(passage.text);
-                        }
-                    } else {
-                        //println!("{}", passage.text);
-                        od.abbreviations.insert(abbr.clone().unwrap(), passage.text);
-                        abbr = None;
+                continue;
+            }
+
+            if options.passage_types.iter().any(|t| passage_type_matches(t, par_type)) {
+
+                // Cleaning can leave nothing of a paragraph. The positions
+                // map the cleaned text back onto the passage.
+                let length = passage.text.chars().count();
+                let mut positions = None;
+                if !options.cleaner.is_empty() {
+                    let (text, mapped) = options.cleaner.clean_mapped(&passage.text, section_type, &mut od.cleaned);
+                    if text.trim().is_empty() {
+                        continue;
                     }
-                    continue;
+                    passage.text = text;
+                    positions = Some(mapped);
                 }
+                let original = |index: usize| positions.as_ref().map_or(index, |p: &Vec<usize>| p[index]) as u32;
+                let span = |start: usize, end: usize| (passage.offset + original(start), original(end) - original(start));
 
-                if par_type == "paragraph" || par_type == "abstract" {
+                let paragraph = if level.is_some() || floating { 0 } else { paragraph };
 
-                    if args.sentences == false {
-                        // Create a JSON paragraph.
+                let sentence_annotations = passage.sentences.iter().flat_map(|sentence| &sentence.annotations);
+                let spans = passage_spans(passage.annotations.iter().chain(sentence_annotations).chain(&document_annotations),
+                                          passage.offset, length, positions.as_deref());
+
+                if !options.sentences {
+                    // Create a JSON paragraph.
+                    let chars = passage.text.chars().count();
+                    let (offset, length) = span(0, chars);
+                    let op = OutputParagraph {
+                        r#type: section_type.to_string(),
+                        passage_type: par_type.to_string(),
+                        text: passage.text.clone(),
+                        headings: headings.clone(),
+                        paragraph,
+                        offset,
+                        length,
+                        annotations: anchor(&spans, 0, chars, &passage.text),
+                        segmentation: None,
+                    };
+                    //let js = serde_json::to_value(&op).unwrap();
+                    //dbg!("{}", js);
+                    od.sentences.push(op);
+                } else {
+                    // The sentences of the file when asked for, if it has
+                    // them for this passage, or else our own.
+                    let provided = if options.bioc_sentences {
+                        bioc_sentences(&passage, length, positions.as_deref())
+                    } else {
+                        None
+                    };
+                    let (sentences, segmentation) = match provided {
+                        Some(sentences) => (sentences, "bioc"),
+                        None => (cut_sentences(&passage.text, options.splitter), "cutters"),
+                    };
+                    let chars: Vec<char> = passage.text.chars().collect();
+                    for (start, end) in sentences {
+                        let text: String = chars[start..end].iter().collect();
+                        let (offset, length) = span(start, end);
                         let op = OutputParagraph {
                             r#type: section_type.to_string(),
-                            text: passage.text.clone()
+                            passage_type: par_type.to_string(),
+                            annotations: anchor(&spans, start, end, &text),
+                            text,
+                            headings: headings.clone(),
+                            paragraph,
+                            offset,
+                            length,
+                            segmentation: Some(segmentation.to_string()),
                         };
-                        //let js = serde_json::to_value(&op).unwrap();
-                        //dbg!("{}", js);
                         od.sentences.push(op);
-                    } else {
-                        for s in cutters::cut(&passage.text, cutters::Language::English) {
-                            let op = OutputParagraph {
-                                r#type: section_type.to_string(),
-                                text: s.str.to_string()
-                            };
-                            od.sentences.push(op);
-                        }
                     }
                 }
-            } else { // has no section_type
-                error!("{}: passage has no section_type.", filename);
             }
-        } // passages
+        } else { // has no section_type
+            error!("{}: passage has no section_type.", filename);
+        }
+    } // passages
+
+    // Documents without front matter are checked on what they have.
+    if !filtered && !options.filter.is_empty() && !options.filter.accepts(&od) {
+        return None;
     }
-    
-    let _remove_simpleref = Regex::new(r"\n\d{1,2}").unwrap();
-    let _remove_latex = Regex::new(r"(?s)\\documentclass.*?\\end\{document\}").unwrap(); // (?s) = multi-line
-    let _remove_figs = Regex::new(r"\(Fig(?:ure|\.)? \d+[a-z]?\)").unwrap();
-    //let remove_figs1 = Regex::new(r"\(Fig\.?\s*ure?\s+\d+[a-z]?(?:,\s*[a-z])?\)").unwrap();
-    //let remove_figs1 = Regex::new(r"\(Fig\.?\s*ure?\s+\d+(?:[a-z](?:,\s*[a-z])?)?\)").unwrap();
-    ////let remove_parens = Regex::new(r"\([^)]*\)").unwrap(); // Takes too much...
-    let _remove_parens = Regex::new(r"\([^)]{1,10}\)").unwrap();
-    let _remove_square = Regex::new(r"\[\s*\d+\s*(,\s*\d+\s*)*\]").unwrap();
-    //Regex::new(r"\[\d+(,\d+)*\]").unwrap(); //Regex::new(r"\[\d+\]").unwrap();
 
-    Ok(od)
+    od.captions = captions.finish();
+
+    // Only the relations between the annotations we kept.
+    let annotation_ids = od.sentences.iter()
+        .flat_map(|sentence| &sentence.annotations)
+        .map(|annotation| annotation.id.as_str())
+        .collect();
+    od.relations = kept_relations(std::mem::take(&mut od.relations), annotation_ids);
+
+    Some(od)
 }
 
+// The sentences from cutters, as character ranges of the text. They are
+// slices of the text, in order, so their byte positions follow from the
+// pointers, and we count the characters from the previous one on.
+fn cut_sentences(text: &str, splitter: Splitter) -> Vec<(usize, usize)> {
+    let mut sentences = vec![];
+    let mut byte = 0;
+    let mut index = 0;
+    for s in cutters::cut(text, splitter.language()) {
+        let start = s.str.as_ptr() as usize - text.as_ptr() as usize;
+        index += text[byte..start].chars().count();
+        let chars = s.str.chars().count();
+        sentences.push((index, index + chars));
+        byte = start + s.str.len();
+        index += chars;
+    }
+    sentences
+}
+
+// The sentences of the BioC passage as character ranges of its cleaned
+// text, without the whitespace around them, and without the ones that
+// cleaning left empty. A sentence without text runs on to the next
+// one. None when the passage has no sentences, or when they are not
+// within the passage, which is reported.
+fn bioc_sentences(passage: &Passage, length: usize, positions: Option<&[usize]>) -> Option<Vec<(usize, usize)>> {
+    if passage.sentences.is_empty() {
+        return None;
+    }
+    let starts: Vec<Option<usize>> = passage.sentences.iter()
+        .map(|sentence| sentence.offset.checked_sub(passage.offset).map(|start| start as usize))
+        .collect();
+    if starts.iter().any(|start| start.is_none_or(|start| start > length)) {
+        error!("Sentences outside their passage at offset {}, splitting it again.", passage.offset);
+        return None;
+    }
+
+    // Original positions to cleaned ones.
+    let cleaned = |index: usize| positions.map_or(index, |p| p.partition_point(|&p| p < index));
+    let chars: Vec<char> = passage.text.chars().collect();
+
+    let mut sentences = vec![];
+    for (n, sentence) in passage.sentences.iter().enumerate() {
+        let start = starts[n].unwrap();
+        let end = if sentence.text.is_empty() {
+            starts.get(n + 1).copied().flatten().unwrap_or(length)
+        } else {
+            start + sentence.text.chars().count()
+        };
+        let (mut start, mut end) = (cleaned(start), cleaned(end.min(length)));
+        while start < end && chars[start].is_whitespace() {
+            start += 1;
+        }
+        while end > start && chars[end - 1].is_whitespace() {
+            end -= 1;
+        }
+        if start < end {
+            sentences.push((start, end));
+        }
+    }
+    Some(sentences)
+}
+
+// The metadata in the infons of the front passage. The BioC files have
+// the PMCID without prefix, and the authors as name_0, name_1, ... like
+// "surname:Smith;given-names:John".
+fn read_front(od: &mut OutputArticle, passage: &Passage) {
+    let infon = |key: &str| passage.infons.get(key).cloned().flatten().filter(|v| !v.is_empty());
+
+    if let Some(year) = infon("year") {
+        od.year = year;
+    }
+    if let Some(pmcid) = infon("article-id_pmc") {
+        od.pmcid = Some(if pmcid.starts_with("PMC") { pmcid } else { format!("PMC{}", pmcid) });
+        // The document id is the PMCID then.
+        od.pmid = "UNK".to_string();
+    }
+    if let Some(pmid) = infon("article-id_pmid") {
+        od.pmid = pmid;
+    }
+    od.title = passage.text.clone();
+    od.doi = infon("article-id_doi");
+    od.journal = infon("journal").or_else(|| infon("source"));
+    od.volume = infon("volume");
+    od.fpage = infon("fpage");
+    od.lpage = infon("lpage");
+    od.license = infon("license");
+    od.article_type = infon("article-type");
+    od.publication_types = infon("publication-types")
+        .map(|kinds| kinds.split("; ").map(|kind| kind.to_string()).collect())
+        .unwrap_or_default();
+    od.authors = read_names(passage);
+}
+
+// A ref passage. The PMCID comes as pub-id_pmcid or pub-id_pmc, with
+// or without its prefix.
+fn read_reference(passage: &Passage) -> Reference {
+    let infon = |key: &str| passage.infons.get(key).cloned().flatten().filter(|v| !v.is_empty());
+
+    let pmcid = infon("pub-id_pmcid").or_else(|| infon("pub-id_pmc"))
+        .map(|pmcid| if pmcid.starts_with("PMC") { pmcid } else { format!("PMC{}", pmcid) });
+    Reference {
+        id: infon("id"),
+        title: passage.text.clone(),
+        pmid: infon("pub-id_pmid"),
+        pmcid,
+        doi: infon("pub-id_doi"),
+        authors: read_names(passage),
+        source: infon("source"),
+        year: infon("year"),
+        volume: infon("volume"),
+        fpage: infon("fpage"),
+        lpage: infon("lpage"),
+    }
+}
+
+// A table_caption passage is kept for the table passage after it, and
+// the table is read from the markup in its xml infon. Tables without
+// markup are left out, their text is in the table passage as it is.
+fn read_table(od: &mut OutputArticle, passage: &Passage, par_type: &str,
+              table_caption: &mut Option<(Option<String>, String)>, filename: &str) {
+    let infon = |key: &str| passage.infons.get(key).cloned().flatten().filter(|v| !v.is_empty());
+    let id = infon("id");
+
+    match par_type {
+        "table_title_caption" | "table_caption" => {
+            // The title and caption of the same table become one.
+            match table_caption {
+                Some((caption_id, text)) if *caption_id == id && id.is_some() => {
+                    text.push(' ');
+                    text.push_str(&passage.text);
+                }
+                _ => *table_caption = Some((id, passage.text.clone())),
+            }
+        }
+        "table" => {
+            let Some(markup) = infon("xml") else {
+                return;
+            };
+            let mut table = match Table::from_xml(&markup) {
+                Ok(table) => table,
+                Err(e) => {
+                    error!("{}: table {}: {}", filename, id.as_deref().unwrap_or("without id"), e);
+                    return;
+                }
+            };
+            if let Some((caption_id, text)) = table_caption.take() {
+                if caption_id == id {
+                    table.caption = Some(text);
+                }
+            }
+            table.label = infon("label");
+            table.id = id;
+            od.tables.push(table);
+        }
+        _ => (),
+    }
+}
+
+// The name_0, name_1, ... infons in order.
+fn read_names(passage: &Passage) -> Vec<Author> {
+    let mut names: Vec<(usize, Author)> = passage.infons.iter()
+        .filter_map(|(key, value)| {
+            let number = key.strip_prefix("name_")?.parse().ok()?;
+            Some((number, parse_name(value.as_deref()?)))
+        })
+        .collect();
+    names.sort_by_key(|(number, _)| *number);
+    names.into_iter().map(|(_, author)| author).collect()
+}
+
+fn parse_name(name: &str) -> Author {
+    let mut author = Author { surname: String::new(), given_names: String::new() };
+    for part in name.split(';') {
+        if let Some(surname) = part.strip_prefix("surname:") {
+            author.surname = surname.to_string();
+        } else if let Some(given_names) = part.strip_prefix("given-names:") {
+            author.given_names = given_names.to_string();
+        }
+    }
+    author
+}
+
+// Section titles are title_1, title_2, ... in the body, and
+// abstract_title_1 in structured abstracts.
+fn heading_level(par_type: &str) -> Option<usize> {
+    let level = par_type.strip_prefix("title_")
+        .or_else(|| par_type.strip_prefix("abstract_title_"))?;
+    level.parse().ok().filter(|level| *level > 0)
+}
+
+/// The section the passages of this type are in, FIG for the figure
+/// captions and TABLE for the tables and their captions.
+pub fn passage_type_section(par_type: &str) -> Option<&'static str> {
+    if par_type.starts_with("fig") {
+        Some("FIG")
+    } else if par_type.starts_with("table") {
+        Some("TABLE")
+    } else {
+        None
+    }
+}
+
+/// Passage types match exactly, or with a level: "title" matches title_1,
+/// title_2 and so on, as well as "title". It also takes the headings of
+/// structured abstracts, abstract_title_1 and so on, which
+/// "abstract_title" selects on their own. "abstract" is only the text.
+pub fn passage_type_matches(wanted: &str, par_type: &str) -> bool {
+    if wanted == "title" && heading_level(par_type).is_some() {
+        return true;
+    }
+    match par_type.strip_prefix(wanted) {
+        Some("") => true,
+        Some(level) => level.strip_prefix('_').is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit())),
+        None => false,
+    }
+}
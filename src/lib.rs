@@ -0,0 +1,59 @@
+//! Extracts the text of PubMed and PMC articles, from BioC (JSON or
+//! XML), JATS and PubMed baseline files, plain or gzipped, on their
+//! own or in tarballs.
+//!
+//! [extract_from_path] reads a file and returns its articles, with the
+//! [ExtractOptions] saying what to take from them.
+//!
+//! ```no_run
+//! use pubmed_rs::{extract_from_path, ExtractOptions};
+//!
+//! let options = ExtractOptions { sentences: true, ..Default::default() };
+//! for article in extract_from_path("PMC1234.xml", &options)? {
+//!     println!("{} {}", article.id(), article.title);
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! For PubMed baseline files with tens of thousands of citations,
+//! [extract_each_from_path] hands the articles to a closure as they are
+//! read. [process_inputs] processes many files, and the members of
+//! tarballs, in parallel, and [Output] writes what it hands on.
+//!
+//! The pubmed-rs command line program is a wrapper around this.
+
+// The library example in the README is compiled as a doctest, so it
+// keeps up with the API.
+#[cfg(doctest)]
+#[doc = include_str!("../README.md")]
+struct ReadmeDoctests;
+
+pub mod annotations;
+pub mod archive;
+pub mod bioc_xml;
+pub mod captions;
+pub mod clean;
+pub mod conll;
+pub mod extract;
+pub mod files;
+pub mod filter;
+pub mod jats;
+pub mod json;
+pub mod medline;
+pub mod output;
+pub mod policy;
+pub mod shard;
+pub mod tables;
+pub mod writer;
+pub mod xml;
+
+pub use annotations::{OutputAnnotation, OutputRelation};
+pub use captions::Caption;
+pub use clean::Cleaner;
+pub use extract::{extract_each_from_bytes, extract_each_from_path, extract_from_bytes, extract_from_path, process_inputs, ExtractOptions, Splitter};
+pub use filter::{ArticleFilter, IdList};
+pub use files::{get_files_from_list, get_files_in_directory, FileOptions};
+pub use output::{Output, OutputOptions};
+pub use policy::SectionPolicy;
+pub use tables::Table;
+pub use json::{OutputArticle, OutputChunk, OutputData, OutputParagraph, Reference};
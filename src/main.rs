@@ -1,21 +1,17 @@
-use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
 use env_logger::Builder;
-use log::{debug, error, info, LevelFilter};
+use log::{debug, error, info, warn, LevelFilter};
 
 use clap::Parser;
 
-use rayon::prelude::*;
+use pubmed_rs::{Cleaner, get_files_from_list, get_files_in_directory, process_inputs, ExtractOptions, FileOptions};
+use pubmed_rs::{ArticleFilter, IdList, SectionPolicy, Splitter};
+use pubmed_rs::json::{passage_type_section, OutputArticle};
+use pubmed_rs::output::{Output, OutputOptions};
+use pubmed_rs::shard::parse_size;
 
-mod json;
-use json::{extract_json_from_json, output_json, OutputArticle, OutputChunk};
-use std::collections::BTreeMap;
-use std::collections::BTreeSet;
-
-use anyhow::Result;
-use std::sync::Mutex;
-use std::sync::Arc;
+use anyhow::{anyhow, Result};
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 
@@ -27,21 +23,73 @@ use std::sync::atomic::Ordering;
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// Filename of the JSON file to parse.
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with_all = ["dirname", "file_list"])]
     filename: Option<String>,
 
     /// Directory name.
     #[arg(short, long)]
     dirname: Option<String>,
 
+    /// Read the files to process from this file, one path or PMCID per
+    /// line, or from standard input with "-". PMCIDs and relative paths
+    /// are looked up in the directory.
+    #[arg(long)]
+    file_list: Option<String>,
+
     /// Output JSON instead of plain text.
     #[arg(short, long, action)]
     json: bool,
 
+    /// Write every article as soon as it has been parsed, instead of
+    /// collecting all of them first. JSON is written one article per line.
+    #[arg(long, action)]
+    stream: bool,
+
+    /// With --stream, write the articles in input order instead of in
+    /// the order they are finished.
+    #[arg(long, action, requires = "stream")]
+    sorted: bool,
+
+    /// Write the output to numbered part files in this directory instead
+    /// of to standard out, with an index of the IDs in every part.
+    #[arg(long)]
+    output_dir: Option<String>,
+
+    /// Start a new part file after this many source articles, or
+    /// abbreviations with --abbreviations. Every article counts once,
+    /// also when it gives many lines, like all its tables with --tables
+    /// or its references with --references.
+    #[arg(long, requires = "output_dir")]
+    shard_articles: Option<usize>,
+
+    /// Start a new part file after this many (uncompressed) bytes, for
+    /// example 500M or 2G.
+    #[arg(long, requires = "output_dir", value_parser = parse_size)]
+    shard_bytes: Option<u64>,
+
+    /// Compress the part files with zstd.
+    #[arg(long, action, requires = "output_dir")]
+    zstd: bool,
+
     /// If specified, maximum number of files to process from directory.
     #[arg(short, long)]
     maxfiles: Option<usize>,
 
+    /// Only read files below the directory matching this glob pattern,
+    /// can be repeated. Default are the xml, nxml, json, gz, tar and tgz
+    /// extensions.
+    #[arg(long)]
+    include: Vec<String>,
+
+    /// Skip files below the directory matching this glob pattern, can be
+    /// repeated.
+    #[arg(long)]
+    exclude: Vec<String>,
+
+    /// Follow symbolic links in the directory, they are skipped otherwise.
+    #[arg(short = 'L', long, action)]
+    follow_symlinks: bool,
+
     /// Include the section names in the output.
     #[arg(short, long, action)]
     sectionnames: bool,
@@ -54,50 +102,117 @@ struct Args {
     #[arg(short = 'S', long, action)]
     sentences: bool,
 
-    // Remove some stuff with hard-coded regular expressions.
-    /*
-    #[arg(short, long, action)]
-    remove: bool,
-     */
+    /// With -S, take the sentences of the BioC passages that have them,
+    /// and split only the others.
+    #[arg(long, action)]
+    bioc_sentences: bool,
+
+    /// The sentence splitter: english, or biomedical, which also knows
+    /// the abbreviations of scientific articles, like Fig. S2, E. coli
+    /// or i.v.
+    #[arg(long, value_parser = ["english", "biomedical"], default_value = "english")]
+    splitter: String,
+
+    /// Remove text from the paragraphs with these cleaning rules,
+    /// separated by commas: latex, figures, citations, parens, footnotes,
+    /// or all (the default when no rules are given).
+    #[arg(short, long, value_delimiter = ',', num_args = 0..=1, default_missing_value = "all")]
+    remove: Vec<String>,
+
+    /// Read more cleaning rules from this TOML or YAML file, applied after
+    /// the --remove ones. Can be repeated.
+    #[arg(long)]
+    rules: Vec<String>,
     
     /// Output only abbreviations
     #[arg(short, long, action)]
     abbreviations: bool,
 
-    /// Allowed sections, separated by spaces
-    #[clap(short = 'A', long, value_delimiter = ' ', num_args = 1..)]
+    /// Output the references of every article as JSON lines, with their
+    /// PMIDs, DOIs, authors and journals, instead of the text.
+    #[arg(long, action, conflicts_with_all = ["abbreviations", "captions"])]
+    references: bool,
+
+    /// Output the figure and table captions of every article as JSON
+    /// lines, with their labels and IDs and the paragraphs that mention
+    /// them, instead of the text.
+    #[arg(long, action, conflicts_with = "abbreviations")]
+    captions: bool,
+
+    /// Output the tables of every article with their rows and columns,
+    /// rebuilt from the table markup: as JSON lines, one table per line,
+    /// or with csv as a CSV file per table in the output directory.
+    #[arg(long, num_args = 0..=1, default_missing_value = "json", value_parser = ["json", "csv"],
+          conflicts_with_all = ["abbreviations", "references", "captions"])]
+    tables: Option<String>,
+
+    /// Output format: text, json (the same as --json), or conll for a
+    /// token per line with its BIO tag from the annotations, which splits
+    /// the sentences.
+    #[arg(long, value_parser = ["text", "json", "conll"],
+          conflicts_with_all = ["abbreviations", "references", "captions", "tables"])]
+    format: Option<String>,
+
+    /// With --format conll, leave out the sentences without annotations.
+    #[arg(long, action)]
+    only_annotated: bool,
+
+    /// Allowed sections, separated by spaces. Only these and ABBR are
+    /// kept, replacing the section policy.
+    #[clap(short = 'A', long, value_delimiter = ' ', num_args = 1.., conflicts_with = "policy")]
     pub allowed: Vec<String>,
-}
 
-// With trait bounds.
-fn get_files_in_directory<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>> {
-    let mut file_paths = Vec::new();
-
-    let args = Args::parse();
-    let mut counter: usize = args.maxfiles.unwrap_or(0);
-
-    for entry in fs::read_dir(path)? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_file() {
-            if let Some(ext) = path.extension() {
-                if ext == "xml" || ext == "json" {
-                    debug!("Added {:?} to file list.", path);
-                    file_paths.push(path);
-                    
-                    if counter > 0 {
-                        counter -= 1;
-                        if counter == 0 {
-                            debug!("Reached file limit.");
-                            break;
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    Ok(file_paths)
+    /// Section policy, a preset (core, abstract-only or everything) or a
+    /// TOML or YAML policy file. The default is core.
+    #[arg(long)]
+    policy: Option<String>,
+
+    /// Keep these section types as well, separated by commas or spaces.
+    #[arg(long, value_delimiter = ',', num_args = 1..)]
+    allow: Vec<String>,
+
+    /// Leave out these section types, separated by commas or spaces.
+    #[arg(long, value_delimiter = ',', num_args = 1..)]
+    deny: Vec<String>,
+
+    /// Only articles published in or after this year.
+    #[arg(long)]
+    year_from: Option<u32>,
+
+    /// Only articles published in or before this year.
+    #[arg(long)]
+    year_to: Option<u32>,
+
+    /// Only articles with a license text containing this, like "CC BY" or
+    /// "CC0", ignoring case. Can be repeated.
+    #[arg(long)]
+    license: Vec<String>,
+
+    /// Only articles from this journal, ignoring case. Can be repeated.
+    #[arg(long)]
+    journal: Vec<String>,
+
+    /// Only articles of these types, like research-article, separated by
+    /// commas. Can be repeated.
+    #[arg(long, value_delimiter = ',')]
+    article_type: Vec<String>,
+
+    /// Only articles with a PMID, PMCID or DOI in this file, one per line.
+    #[arg(long)]
+    include_ids: Option<String>,
+
+    /// Leave out the articles with a PMID, PMCID or DOI in this file, one
+    /// per line.
+    #[arg(long)]
+    exclude_ids: Option<String>,
+
+    /// BioC passage types to output, separated by commas or spaces, like
+    /// title (all heading levels, also those of structured abstracts),
+    /// abstract_title (only those), fig_caption, table_caption, table or
+    /// footnote. Other types match exactly. Captions and tables allow the
+    /// FIG or TABLE section.
+    #[arg(long, value_delimiter = ',', num_args = 1.., default_values = ["paragraph", "abstract"])]
+    passage_types: Vec<String>,
 }
 
 // With and without par_iter()
@@ -109,136 +224,157 @@ fn main() -> Result<()> { //, Box<dyn std::error::Error>> {
         .filter_level(LevelFilter::Info) //LevelFilter::max()) // Info
         .init();
     
-    let args = Args::parse();
+    let mut args = Args::parse();
     info!("{:?}", args);
+    if args.format.as_deref() == Some("json") {
+        args.json = true;
+    }
+    let conll = args.format.as_deref() == Some("conll");
+    if args.only_annotated && !conll {
+        return Err(anyhow!("--only-annotated only works with --format conll"));
+    }
 
-    let allowed: BTreeSet<String> = args.allowed.into_iter().collect();
-    
-    // Check if dirname is not none first. If it exists, we parse all the
-    // files in the directory.
-
-    // We should collect the abbreviations first, before printing to
-    // prevent doubles.
-    let abbreviations = Mutex::new(BTreeMap::new());
-
-    if args.dirname.is_some() {
-        let dirfiles = get_files_in_directory(args.dirname.unwrap());
-        let file_counter = Arc::new(AtomicUsize::new(0));
-
-        // Mutex needed.
-        let oc = Mutex::new(OutputChunk { 
-            articles: BTreeMap::new()
-        });
-
-        match dirfiles {
-            Ok(files) => {
-                // iter(), par_iter() {
-                files.par_iter().for_each(|file| { // Note that the order is unknown.
-                    let filename = file.file_name().unwrap().to_str().unwrap();
-                    debug!("Starting {}.", filename);
-                    match extract_json_from_json(file, filename, &allowed) {
-                        Ok(texts) => {
-                            if args.abbreviations == true {
-                                let mut abbr = abbreviations.lock().unwrap();
-                                add_abbreviations(&mut abbr, texts);
-                            } else {
-                                let pmid = texts.pmid.clone();
-                                let mut oc1 = oc.lock().unwrap();
-                                oc1.articles.insert(pmid, texts);
-                            }
-                            debug!("Output {} ok.", filename);
-                            info!("Processed {} {}", filename, file_counter.load(Ordering::SeqCst));
-                        },
-                        Err(e) => error!("Error reading or parsing {}: {}",
-                            file.file_name().unwrap().to_str().unwrap(),
-                            e)
-                    }
-                    file_counter.fetch_add(1, Ordering::SeqCst);
-                });
-            }
-            Err(e) => error!("Failed to read directory: {}", e)
-        } // match dirfiles
-        info!("Total files processed: {}", file_counter.load(Ordering::SeqCst));
-        // output, and/or create chunks?
-        let oc1: &OutputChunk = &*oc.lock().unwrap();
-        if args.json {
-            output_json(oc1);
-        } else {
-            for (pmid, article) in &oc1.articles {
-                output(pmid, article);
-            }
+    let mut cleaner = Cleaner::builtin(&args.remove)?;
+    for rules in &args.rules {
+        cleaner.load_rules(rules)?;
+    }
+    let mut sections = if !args.allowed.is_empty() {
+        SectionPolicy::only(args.allowed.iter().cloned())
+    } else {
+        SectionPolicy::load(args.policy.as_deref().unwrap_or("core"))?
+    };
+    sections.allow_sections(args.allow.iter().cloned());
+    sections.deny_sections(args.deny.iter().cloned());
+
+    // Captions and tables are in the FIG and TABLE sections, which the
+    // core policy drops. Asking for them allows their section, unless it
+    // was denied on the command line.
+    for passage_type in &args.passage_types {
+        let Some(section) = passage_type_section(passage_type) else {
+            continue;
+        };
+        if args.deny.iter().any(|denied| denied == section) {
+            warn!("--passage-types {} gives nothing with the {} section denied.", passage_type, section);
+        } else if !sections.allows(section) {
+            info!("Allowing the {} section for --passage-types {}.", section, passage_type);
+            sections.allow_sections([section.to_string()]);
         }
     }
 
-    // We supplied a single filename. Should output be OutputChunk or
-    // OutputArticle?
-    if args.filename.is_some() {
-        let path_name = args.filename.unwrap();
-
-        match extract_json_from_json(path_name.clone(), &path_name, &allowed) {
-            Ok(texts) => {
-                if args.abbreviations == true {
-                    let mut abbr = abbreviations.lock().unwrap();
-                    add_abbreviations(&mut abbr, texts);
-                    //dbg!("Output abbreviations.");
-                    //output_abbreviations(&path_name, texts);
-                } else {
-                    if args.json {
-                        output_json(&texts);
-                    } else {
-                        output(&path_name, &texts);
-                    }
+    let mut filter = ArticleFilter::default();
+    filter.year_from = args.year_from;
+    filter.year_to = args.year_to;
+    filter.licenses = args.license.clone();
+    filter.journals = args.journal.clone();
+    filter.article_types = args.article_type.clone();
+    filter.include_ids = args.include_ids.as_ref().map(IdList::load).transpose()?;
+    filter.exclude_ids = args.exclude_ids.as_ref().map(IdList::load).transpose()?;
+
+    let options = ExtractOptions {
+        sections,
+        passage_types: args.passage_types.clone(),
+        sentences: args.sentences || conll,
+        bioc_sentences: args.bioc_sentences,
+        splitter: if args.splitter == "biomedical" { Splitter::Biomedical } else { Splitter::English },
+        cleaner,
+        filter,
+        references: args.references,
+        captions: args.captions,
+        tables: args.tables.is_some(),
+    };
+    let file_options = FileOptions {
+        include: args.include.clone(),
+        exclude: args.exclude.clone(),
+        follow_symlinks: args.follow_symlinks,
+        maxfiles: args.maxfiles,
+    };
+    let output = Output::new(OutputOptions {
+        json: args.json,
+        filenames: args.filenames,
+        sectionnames: args.sectionnames,
+        references: args.references,
+        captions: args.captions,
+        tables: args.tables.is_some(),
+        tables_csv: args.tables.as_deref() == Some("csv"),
+        conll,
+        only_annotated: args.only_annotated,
+        abbreviations: args.abbreviations,
+        stream: args.stream,
+        sorted: args.sorted,
+        output_dir: args.output_dir.as_ref().map(PathBuf::from),
+        zstd: args.zstd,
+        shard_articles: args.shard_articles,
+        shard_bytes: args.shard_bytes,
+        input: args.filename.clone(),
+    })?;
+
+    // A single file, or the files from a list, which are looked up in
+    // the directory, or all the files in the directory.
+    let mut missing = vec![];
+    let files = if let Some(filename) = &args.filename {
+        Ok(vec![PathBuf::from(filename)])
+    } else if let Some(file_list) = &args.file_list {
+        get_files_from_list(file_list, args.dirname.as_deref(), &file_options)
+            .map(|(files, not_found)| {
+                missing = not_found;
+                files
+            })
+    } else if let Some(dirname) = &args.dirname {
+        get_files_in_directory(dirname, &file_options)
+    } else {
+        Ok(vec![])
+    };
+
+    let file_counter = AtomicUsize::new(0);
+
+    // Called for every batch of articles of a file, last is set for
+    // the last one.
+    let handle = |seq: usize, filename: &str, result: Result<Vec<OutputArticle>>, last: bool| {
+        let articles = match result {
+            Ok(articles) => {
+                if last {
+                    debug!("Output {} ok.", filename);
+                    info!("Processed {} {}", filename, file_counter.load(Ordering::SeqCst));
                 }
+                articles
             },
-            Err(e) => error!("Error reading or parsing JSON: {}", e),
+            Err(e) => {
+                error!("Error reading or parsing {}: {}", filename, e);
+                vec![]
+            }
+        };
+        if last {
+            file_counter.fetch_add(1, Ordering::SeqCst);
         }
+        output.add(seq, articles, last);
+    };
+
+    match files {
+        Ok(files) => {
+            let files = files.into_iter()
+                .map(|file| {
+                    // A single file is named as given, the others by
+                    // their file name.
+                    let filename = match &args.filename {
+                        Some(filename) => filename.clone(),
+                        None => file.file_name().unwrap().to_str().unwrap().to_string(),
+                    };
+                    (file, filename)
+                })
+                .collect();
+            process_inputs(files, &options, &handle);
+        }
+        Err(e) => error!("Failed to read directory or file list: {}", e)
     }
-
-    if args.abbreviations == true {
-        let abbr = abbreviations.lock().unwrap();
-        output_abbreviations(&abbr);
+    if args.filename.is_none() {
+        info!("Total files processed: {}", file_counter.load(Ordering::SeqCst));
     }
-    
-    Ok(())
-}
-
-// ================================================================
-// Output
-// ================================================================
-
-// Print section-type and text, with optinal filenames/section-types.
-fn output(filename: &str, texts: &OutputArticle) {
-    let args = Args::parse();
-    
-    let paragraphs = &texts.sentences;
-
-    for par in paragraphs {
-        if args.filenames == true {
-            print!("{}\t", filename);
+    if !missing.is_empty() {
+        error!("{} entries in the file list were not found:", missing.len());
+        for entry in &missing {
+            error!("Not found: {}", entry);
         }
-        if args.sectionnames == true {
-            let par_type = &par.r#type;
-            print!("{}\t", par_type);
-        }
-        let par_text = &par.text;
-        println!("{}", par_text); 
-    }    
-}
-
-// Convert the Value to an OutputArticle, and add the abbreviations
-// to the BTreeMap.
-fn add_abbreviations(abbreviations: &mut BTreeMap<String, String>, article: OutputArticle) {
-    let new_abbreviations = article.abbreviations;
-    for (k, v) in new_abbreviations.into_iter() {
-        abbreviations.entry(k.clone()).or_insert_with(String::new).push_str(&v);
     }
-}
 
-// Loop and print, they are sorted.
-fn output_abbreviations(abbreviations: &BTreeMap<String, String>) {
-    println!("output_abbreviations");
-    for (key, value) in abbreviations.iter() {
-        println!("{}\t{}", key, value);
-    }
+    output.finish(&options)
 }
-
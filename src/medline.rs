@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use anyhow::{anyhow, Result};
+
+use crate::json::{Document, Passages};
+use crate::xml::{self, Element};
+
+// ===========================================================================
+
+/*
+    PubMed baseline and update files (pubmedNNNNn.xml.gz). These hold
+    tens of thousands of citations each, so we don't build a tree of
+    the whole file, but read one <PubmedArticle> at a time and turn it
+    into a BioC document with a front passage and the abstract.
+
+    <PubmedArticle>
+      <MedlineCitation>
+        <PMID>
+        <Article>
+          <Journal> ... <PubDate><Year>
+          <ArticleTitle>
+          <Abstract><AbstractText Label="..">*
+      <PubmedData><ArticleIdList><ArticleId IdType="doi">
+*/
+// PubMed publication types with the JATS article-type of PMC, the more
+// specific ones first.
+const ARTICLE_TYPES: &[(&str, &str)] = &[
+    ("Retraction of Publication", "retraction"),
+    ("Published Erratum", "correction"),
+    ("Systematic Review", "review-article"),
+    ("Review", "review-article"),
+    ("Case Reports", "case-report"),
+    ("Editorial", "editorial"),
+    ("Letter", "letter"),
+    ("Comment", "article-commentary"),
+    ("News", "news"),
+    ("Journal Article", "research-article"),
+];
+
+pub struct MedlineReader<R: BufRead> {
+    reader: Reader<R>,
+    buf: Vec<u8>,
+    done: bool,
+    // The PMID of the last citation read, to say where a broken one is.
+    last_pmid: Option<String>,
+}
+
+impl<R: BufRead> MedlineReader<R> {
+    pub fn new(reader: R) -> Self {
+        // Mismatched end tags are not worth losing the rest of the file
+        // over, they just close the element.
+        let mut reader = Reader::from_reader(reader);
+        reader.check_end_names(false);
+        MedlineReader {
+            reader,
+            buf: Vec::new(),
+            done: false,
+            last_pmid: None,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for MedlineReader<R> {
+    type Item = Result<Document>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Everything outside the citations (and the DeleteCitation lists
+        // in the update files) is skipped.
+        while !self.done {
+            self.buf.clear();
+            match self.reader.read_event_into(&mut self.buf) {
+                Ok(Event::Start(start)) if start.name().as_ref() == b"PubmedArticle" => {
+                    // A citation we cannot make sense of is returned as an
+                    // error, and we go on with the next one. quick-xml
+                    // cannot go on after an XML error though, so that ends
+                    // the file, after the citations before it.
+                    let start = start.into_owned();
+                    let mut buf = Vec::new();
+                    let citation = match xml::read_element(&mut self.reader, &start, &mut buf) {
+                        Ok(element) => {
+                            let pmid = element.path(&["MedlineCitation", "PMID"]).map(|p| p.clean_text());
+                            let citation = parse_citation(&element);
+                            if pmid.is_some() {
+                                self.last_pmid = pmid;
+                            }
+                            citation
+                        },
+                        Err(e) => {
+                            self.done = true;
+                            match &self.last_pmid {
+                                Some(pmid) => Err(anyhow!("the citation after PMID {}: {}", pmid, e)),
+                                None => Err(anyhow!("the first citation: {}", e)),
+                            }
+                        },
+                    };
+                    return Some(citation);
+                },
+                Ok(Event::Eof) => self.done = true,
+                Ok(_) => (),
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                },
+            }
+        }
+        None
+    }
+}
+
+fn parse_citation(pubmed_article: &Element) -> Result<Document> {
+    let citation = pubmed_article.child("MedlineCitation")
+        .ok_or_else(|| anyhow!("<PubmedArticle> without <MedlineCitation>"))?;
+    let pmid = citation.child("PMID")
+        .map(|p| p.clean_text())
+        .ok_or_else(|| anyhow!("<MedlineCitation> without <PMID>"))?;
+    let article = citation.child("Article")
+        .ok_or_else(|| anyhow!("PMID {}: no <Article>", pmid))?;
+
+    let mut infons = HashMap::new();
+    infons.insert("article-id_pmid".to_string(), Some(pmid.clone()));
+
+    let id_list = pubmed_article.path(&["PubmedData", "ArticleIdList"]);
+    if let Some(doi) = id_list.and_then(|ids| ids.children_named("ArticleId").find(|id| id.attr("IdType") == Some("doi"))) {
+        infons.insert("article-id_doi".to_string(), Some(doi.clean_text()));
+    }
+    if let Some(year) = article.path(&["Journal", "JournalIssue", "PubDate"]).and_then(pub_year) {
+        infons.insert("year".to_string(), Some(year));
+    }
+    if let Some(journal) = article.path(&["Journal", "Title"]) {
+        infons.insert("journal".to_string(), Some(journal.clean_text()));
+    }
+    if let Some(volume) = article.path(&["Journal", "JournalIssue", "Volume"]) {
+        infons.insert("volume".to_string(), Some(volume.clean_text()));
+    }
+    // Pages like "123-9", the last page is abbreviated, so we only keep
+    // the first.
+    if let Some(pages) = article.path(&["Pagination", "MedlinePgn"]) {
+        let pages = pages.clean_text();
+        if let Some(fpage) = pages.split('-').next().filter(|p| !p.is_empty()) {
+            infons.insert("fpage".to_string(), Some(fpage.to_string()));
+        }
+    }
+    // A citation has all the types that apply, "Journal Article" nearly
+    // always among them. The most specific one is taken as the JATS
+    // article type, and all of them are kept.
+    let kinds: Vec<String> = article.child("PublicationTypeList").into_iter()
+        .flat_map(|list| list.children_named("PublicationType"))
+        .map(|kind| kind.clean_text())
+        .collect();
+    let article_type = ARTICLE_TYPES.iter()
+        .find(|(kind, _)| kinds.iter().any(|k| k == kind))
+        .map(|(_, article_type)| article_type.to_string());
+    if let Some(article_type) = article_type {
+        infons.insert("article-type".to_string(), Some(article_type));
+    }
+    if !kinds.is_empty() {
+        infons.insert("publication-types".to_string(), Some(kinds.join("; ")));
+    }
+
+    // Collective names have no surname, and are left out.
+    let authors = article.child("AuthorList").into_iter()
+        .flat_map(|list| list.children_named("Author"))
+        .filter_map(|author| Some((author.child("LastName")?, author.child("ForeName"))));
+    for (n, (surname, given)) in authors.enumerate() {
+        let given = given.map(|g| g.clean_text()).unwrap_or_default();
+        infons.insert(format!("name_{}", n), Some(format!("surname:{};given-names:{}", surname.clean_text(), given)));
+    }
+
+    let mut passages = Passages::default();
+    let title = article.child("ArticleTitle").map(|t| t.clean_text()).unwrap_or_default();
+    passages.push("TITLE", "front", title, infons);
+
+    let keywords: Vec<String> = citation.children_named("KeywordList")
+        .flat_map(|list| list.children_named("Keyword"))
+        .map(|keyword| keyword.clean_text())
+        .collect();
+    passages.push_text("KEYWORD", "keyword", keywords.join("; "));
+
+    // Structured abstracts have a label on every part, which we keep as
+    // an abstract title.
+    if let Some(abstr) = article.child("Abstract") {
+        for text in abstr.children_named("AbstractText") {
+            if let Some(label) = text.attr("Label") {
+                passages.push_text("ABSTRACT", "abstract_title_1", label.to_string());
+            }
+            passages.push_text("ABSTRACT", "abstract", text.clean_text());
+        }
+    }
+
+    Ok(Document {
+        id: pmid,
+        infons: HashMap::new(),
+        passages: passages.passages,
+        annotations: vec![],
+        relations: vec![],
+    })
+}
+
+// Either <Year>, or a free-form <MedlineDate> like "1998 Dec-1999 Jan".
+fn pub_year(date: &Element) -> Option<String> {
+    if let Some(year) = date.child("Year") {
+        return Some(year.clean_text());
+    }
+    let medline_date = date.child("MedlineDate")?.clean_text();
+    medline_date.split_whitespace()
+        .next()
+        .map(|y| y.chars().take(4).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn citations() {
+        let data = r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE PubmedArticleSet PUBLIC "-//NLM//DTD PubMedArticle, 1st January 2024//EN" "https://dtd.nlm.nih.gov/ncbi/pubmed/out/pubmed_240101.dtd">
+<PubmedArticleSet>
+<PubmedArticle><MedlineCitation Status="MEDLINE" Owner="NLM"><PMID Version="1">1</PMID>
+<Article PubModel="Print"><Journal><JournalIssue><Volume>13</Volume><PubDate><Year>1975</Year><Month>Jun</Month></PubDate></JournalIssue><Title>Biochemical medicine</Title></Journal>
+<ArticleTitle>Formate assay in body fluids: application in methanol poisoning.</ArticleTitle>
+<Pagination><MedlinePgn>117-26</MedlinePgn></Pagination>
+<Abstract><AbstractText Label="BACKGROUND">Some background.</AbstractText><AbstractText Label="RESULTS">Some results.</AbstractText></Abstract>
+<AuthorList><Author><LastName>Makar</LastName><ForeName>A B</ForeName></Author><Author><CollectiveName>A group</CollectiveName></Author></AuthorList>
+<PublicationTypeList><PublicationType UI="D016428">Journal Article</PublicationType><PublicationType UI="D016454">Review</PublicationType></PublicationTypeList>
+</Article><KeywordList Owner="NOTNLM"><Keyword>formate</Keyword></KeywordList></MedlineCitation>
+<PubmedData><ArticleIdList><ArticleId IdType="pubmed">1</ArticleId><ArticleId IdType="doi">10.1016/0006-2944(75)90147-7</ArticleId></ArticleIdList></PubmedData></PubmedArticle>
+<PubmedArticle><MedlineCitation><PMID Version="1">2</PMID>
+<Article><Journal><JournalIssue><PubDate><MedlineDate>1998 Dec-1999 Jan</MedlineDate></PubDate></JournalIssue></Journal>
+<ArticleTitle>No abstract.</ArticleTitle></Article></MedlineCitation></PubmedArticle>
+<DeleteCitation><PMID Version="1">3</PMID></DeleteCitation>
+</PubmedArticleSet>"#;
+
+        let documents = MedlineReader::new(data.as_bytes())
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        assert!(documents.len() == 2);
+        assert!(documents[0].id == "1");
+
+        let front = &documents[0].passages[0];
+        assert!(front.text == "Formate assay in body fluids: application in methanol poisoning.");
+        assert!(front.infons["year"] == Some("1975".to_string()));
+        assert!(front.infons["article-id_doi"] == Some("10.1016/0006-2944(75)90147-7".to_string()));
+        assert!(front.infons["journal"] == Some("Biochemical medicine".to_string()));
+        assert!(front.infons["volume"] == Some("13".to_string()));
+        assert!(front.infons["fpage"] == Some("117".to_string()));
+        assert!(front.infons["article-type"] == Some("review-article".to_string()));
+        assert!(front.infons["publication-types"] == Some("Journal Article; Review".to_string()));
+        assert!(front.infons["name_0"] == Some("surname:Makar;given-names:A B".to_string()));
+        assert!(!front.infons.contains_key("name_1"));
+
+        let texts: Vec<&str> = documents[0].passages.iter().map(|p| p.text.as_str()).collect();
+        assert!(texts[1..] == ["formate", "BACKGROUND", "Some background.", "RESULTS", "Some results."]);
+
+        assert!(documents[1].passages.len() == 1);
+        assert!(documents[1].passages[0].infons["year"] == Some("1998".to_string()));
+    }
+
+    #[test]
+    fn broken() {
+        let data = r#"<PubmedArticleSet>
+<PubmedArticle><MedlineCitation><PMID>1</PMID><Article><ArticleTitle>First.</ArticleTitle></Article></MedlineCitation></PubmedArticle>
+<PubmedArticle><MedlineCitation><PMID>2</PMID></MedlineCitation></PubmedArticle>
+<PubmedArticle><MedlineCitation><PMID>3</PMID><Article><ArticleTitle>Broken.</Title></Article></MedlineCitation></PubmedArticle>
+<PubmedArticle><MedlineCitation><PMID>4</PMID><Article><ArticleTitle>Last.</ArticleTitle></Article></MedlineCitation></PubmedArticle>
+<PubmedArticle><MedlineCitation><PMID>5</PMID><Article><ArticleTitle>Unreadable <!x></ArticleTitle></Article></MedlineCitation></PubmedArticle>
+<PubmedArticle><MedlineCitation><PMID>6</PMID><Article><ArticleTitle>Lost.</ArticleTitle></Article></MedlineCitation></PubmedArticle>
+</PubmedArticleSet>"#;
+
+        let results: Vec<Result<Document>> = MedlineReader::new(data.as_bytes()).collect();
+        let ids: Vec<String> = results.iter().filter_map(|r| r.as_ref().ok()).map(|d| d.id.clone()).collect();
+        // The mismatched end tag is tolerated, the citation without an
+        // <Article> skipped, and the XML error ends the file.
+        assert!(ids == ["1", "3", "4"]);
+        assert!(results.len() == 5);
+        assert!(results[1].as_ref().unwrap_err().to_string().contains("PMID 2"));
+        assert!(results[4].as_ref().unwrap_err().to_string().contains("after PMID 4"));
+    }
+}
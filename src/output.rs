@@ -0,0 +1,386 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+
+use log::{error, info};
+
+use anyhow::{anyhow, Result};
+
+use crate::conll::write_conll;
+use crate::extract::ExtractOptions;
+use crate::json::{output_json, OutputArticle, OutputChunk, OutputData};
+use crate::shard::ShardWriter;
+use crate::writer::{start_writer, BatchSender, Destination};
+
+// ===========================================================================
+
+/*
+    Writing the articles, the way the command line program does. The
+    articles come in batches from process_inputs(), and depending on the
+    options they are:
+
+    - streamed to the writer thread, to standard out or part files,
+    - collected and written at the end, sorted by ID unless they come
+      from a single file,
+    - written as a CSV file per table, or
+    - only used for their abbreviations, which are written at the end.
+
+    References, captions, tables and CoNLL are written as records, an
+    article at a time, also when they are collected.
+*/
+
+/// How the articles are written. The defaults give the plain text of
+/// the paragraphs on standard out, after all articles have been read.
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    /// JSON instead of plain text.
+    pub json: bool,
+
+    /// Put the file name, and the section type, in front of every line
+    /// of text.
+    pub filenames: bool,
+    pub sectionnames: bool,
+
+    /// Only the references, captions or tables of the articles, as JSON
+    /// lines.
+    pub references: bool,
+    pub captions: bool,
+    pub tables: bool,
+
+    /// With tables, write a CSV file per table to the output directory.
+    pub tables_csv: bool,
+
+    /// CoNLL, a token per line with its BIO tag, optionally only for the
+    /// sentences with annotations.
+    pub conll: bool,
+    pub only_annotated: bool,
+
+    /// Only the abbreviations of all articles together.
+    pub abbreviations: bool,
+
+    /// Write every article as soon as it has been read, in input order
+    /// when sorted.
+    pub stream: bool,
+    pub sorted: bool,
+
+    /// Write numbered part files in this directory instead of to
+    /// standard out, compressed with zstd, and starting a new part after
+    /// a number of articles or bytes.
+    pub output_dir: Option<PathBuf>,
+    pub zstd: bool,
+    pub shard_articles: Option<usize>,
+    pub shard_bytes: Option<u64>,
+
+    /// The name of the file when a single one is read. Its text lines
+    /// get this name instead of the article ID, and a file with one
+    /// article is written as that article instead of as a chunk.
+    pub input: Option<String>,
+}
+
+impl OutputOptions {
+    // The articles are written one by one, as records.
+    fn records(&self) -> bool {
+        self.references || self.captions || self.tables || self.conll
+    }
+
+    fn extension(&self) -> &'static str {
+        if self.abbreviations {
+            "tsv"
+        } else if self.conll {
+            "conll"
+        } else if self.json || self.references || self.captions || self.tables {
+            "jsonl"
+        } else {
+            "txt"
+        }
+    }
+}
+
+/// Takes the articles as they are read and writes them, see
+/// OutputOptions. add() can be called from many threads.
+pub struct Output {
+    options: OutputOptions,
+    tables_dir: Option<PathBuf>,
+    stream: Option<(BatchSender, JoinHandle<io::Result<()>>)>,
+    articles: Mutex<Vec<OutputArticle>>,
+    abbreviations: Mutex<BTreeMap<String, String>>,
+    // Characters removed by every cleaning rule, over all articles.
+    cleaned: Mutex<BTreeMap<String, usize>>,
+}
+
+impl Output {
+    pub fn new(options: OutputOptions) -> Result<Output> {
+        let tables_dir = if options.tables_csv {
+            let Some(output_dir) = &options.output_dir else {
+                return Err(anyhow!("--tables csv writes the tables to --output-dir"));
+            };
+            fs::create_dir_all(output_dir)?;
+            Some(output_dir.clone())
+        } else {
+            None
+        };
+
+        // When streaming, articles go straight to the writer thread
+        // instead of being collected.
+        let stream = if options.stream && !options.abbreviations && tables_dir.is_none() {
+            let format_options = options.clone();
+            Some(start_writer(options.sorted, open_destination(&options)?, move |out, article| {
+                format_article(out, article, &format_options)
+            }))
+        } else {
+            None
+        };
+
+        Ok(Output {
+            options,
+            tables_dir,
+            stream,
+            articles: Mutex::default(),
+            abbreviations: Mutex::default(),
+            cleaned: Mutex::default(),
+        })
+    }
+
+    /// A batch of articles of the input with this sequence number, last
+    /// is set for the last one. Failed inputs are added too, without
+    /// articles, to keep the sorted output going.
+    pub fn add(&self, seq: usize, articles: Vec<OutputArticle>, last: bool) {
+        add_cleaned(&mut self.cleaned.lock().unwrap(), &articles);
+
+        if let Some(tables_dir) = &self.tables_dir {
+            for article in &articles {
+                if let Err(e) = write_tables(tables_dir, article) {
+                    error!("Error writing the tables of {}: {}", article.id(), e);
+                }
+            }
+            return;
+        }
+        if let Some((sender, _)) = &self.stream {
+            // If the writer has stopped, the error is reported by finish().
+            let _ = sender.send((seq, articles, last));
+            return;
+        }
+        for article in articles {
+            if self.options.abbreviations {
+                add_abbreviations(&mut self.abbreviations.lock().unwrap(), article);
+            } else {
+                self.articles.lock().unwrap().push(article);
+            }
+        }
+    }
+
+    /// Writes what was collected, waits for the streamed output, and
+    /// logs what the filters and cleaning rules of the options did.
+    pub fn finish(self, extract_options: &ExtractOptions) -> Result<()> {
+        let cleaned = std::mem::take(&mut *self.cleaned.lock().unwrap());
+        let result = self.write_rest();
+        log_summary(extract_options, &cleaned);
+        result
+    }
+
+    fn write_rest(self) -> Result<()> {
+        let options = &self.options;
+        if let Some((sender, writer)) = self.stream {
+            drop(sender); // closes the channel, so the writer finishes.
+            writer.join().unwrap().map_err(|e| anyhow!("Error writing output: {}", e))?;
+            return Ok(());
+        }
+        if options.abbreviations {
+            let abbreviations = self.abbreviations.into_inner().unwrap();
+            if options.output_dir.is_some() {
+                write_abbreviations(open_destination(options)?, &abbreviations)?;
+            } else {
+                output_abbreviations(&abbreviations);
+            }
+            return Ok(());
+        }
+        if self.tables_dir.is_some() {
+            return Ok(());
+        }
+
+        // The articles of a single file are written as they came, the
+        // others sorted by ID.
+        let mut articles = self.articles.into_inner().unwrap();
+        if options.output_dir.is_some() || options.records() {
+            if options.input.is_none() {
+                articles = sorted(articles);
+            }
+            write_articles(open_destination(options)?, articles.iter(), options)?;
+        } else if options.json {
+            if options.input.is_some() && articles.len() == 1 {
+                output_json(&articles[0]);
+            } else {
+                let chunk = OutputChunk { articles: articles.into_iter().map(|a| (a.id().to_string(), a)).collect() };
+                output_json(&chunk);
+            }
+        } else {
+            let mut out = io::stdout().lock();
+            for article in &sorted(articles) {
+                output(&mut out, options.input.as_deref().unwrap_or(article.id()), article, options)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Sorted by ID, and only the last article with the same ID.
+fn sorted(articles: Vec<OutputArticle>) -> Vec<OutputArticle> {
+    let by_id: BTreeMap<String, OutputArticle> = articles.into_iter().map(|a| (a.id().to_string(), a)).collect();
+    by_id.into_values().collect()
+}
+
+fn log_summary(options: &ExtractOptions, cleaned: &BTreeMap<String, usize>) {
+    let rejected = options.filter.rejected();
+    for name in options.filter.names() {
+        info!("Rejected {} articles with the {} filter.", rejected.get(name).unwrap_or(&0), name);
+    }
+    let unseen = options.filter.unseen();
+    if !unseen.is_empty() {
+        error!("{} IDs on the include list were not seen:", unseen.len());
+        for id in &unseen {
+            error!("Not seen: {}", id);
+        }
+    }
+
+    for rule in &options.cleaner.rules {
+        info!("Removed {} characters with cleaning rule {}.", cleaned.get(&rule.name).unwrap_or(&0), rule.name);
+    }
+}
+
+// Print section-type and text, with optinal filenames/section-types.
+fn output<W: Write>(out: &mut W, filename: &str, texts: &OutputArticle, options: &OutputOptions) -> io::Result<()> {
+    let paragraphs = &texts.sentences;
+
+    for par in paragraphs {
+        if options.filenames {
+            write!(out, "{}\t", filename)?;
+        }
+        if options.sectionnames {
+            let par_type = &par.r#type;
+            write!(out, "{}\t", par_type)?;
+        }
+        let par_text = &par.text;
+        writeln!(out, "{}", par_text)?;
+    }
+    Ok(())
+}
+
+// Part files in the output directory, or standard out.
+fn open_destination(options: &OutputOptions) -> Result<Destination> {
+    let Some(output_dir) = &options.output_dir else {
+        return Ok(Destination::stdout());
+    };
+    let shards = ShardWriter::new(output_dir.clone(), options.extension(), options.zstd,
+                                  options.shard_articles, options.shard_bytes)?;
+    Ok(Destination::Shards(shards))
+}
+
+/// One article as a JSON line, or as its plain text lines, or its
+/// references, captions, tables or CoNLL, as the options say.
+pub fn format_article(out: &mut Vec<u8>, article: &OutputArticle, options: &OutputOptions) -> io::Result<()> {
+    if options.references {
+        writeln!(out, "{}", article.reference_list().to_json_line())
+    } else if options.captions {
+        writeln!(out, "{}", article.caption_list().to_json_line())
+    } else if options.tables {
+        for table in article.table_records() {
+            writeln!(out, "{}", table.to_json_line())?;
+        }
+        Ok(())
+    } else if options.conll {
+        write_conll(out, article, options.only_annotated)
+    } else if options.json {
+        writeln!(out, "{}", article.to_json_line())
+    } else {
+        output(out, options.input.as_deref().unwrap_or(article.id()), article, options)
+    }
+}
+
+fn write_articles<'a, I>(mut destination: Destination, articles: I, options: &OutputOptions) -> io::Result<()>
+where
+    I: Iterator<Item = &'a OutputArticle>
+{
+    let mut record = Vec::new();
+    for article in articles {
+        record.clear();
+        format_article(&mut record, article, options)?;
+        destination.write_record(article.id(), &record)?;
+    }
+    destination.finish()
+}
+
+// Every table as a CSV file of its own, named after the article and
+// the table id, like PMC10546722-T1.csv, or its number without an id.
+fn write_tables(dir: &Path, article: &OutputArticle) -> io::Result<()> {
+    for (number, table) in article.tables.iter().enumerate() {
+        let name = match &table.id {
+            Some(id) => id.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect(),
+            None => (number + 1).to_string(),
+        };
+        fs::write(dir.join(format!("{}-{}.csv", article.id(), name)), table.to_csv())?;
+    }
+    Ok(())
+}
+
+// Add the abbreviations of the article to the BTreeMap.
+fn add_abbreviations(abbreviations: &mut BTreeMap<String, String>, article: OutputArticle) {
+    let new_abbreviations = article.abbreviations;
+    for (k, v) in new_abbreviations.into_iter() {
+        abbreviations.entry(k.clone()).or_default().push_str(&v);
+    }
+}
+
+fn add_cleaned(cleaned: &mut BTreeMap<String, usize>, articles: &[OutputArticle]) {
+    for article in articles {
+        for (rule, count) in &article.cleaned {
+            *cleaned.entry(rule.clone()).or_default() += count;
+        }
+    }
+}
+
+// The abbreviations are the records here, and the index lists them.
+fn write_abbreviations(mut destination: Destination, abbreviations: &BTreeMap<String, String>) -> io::Result<()> {
+    for (key, value) in abbreviations.iter() {
+        destination.write_record(key, format!("{}\t{}\n", key, value).as_bytes())?;
+    }
+    destination.finish()
+}
+
+// Loop and print, they are sorted.
+fn output_abbreviations(abbreviations: &BTreeMap<String, String>) {
+    println!("output_abbreviations");
+    for (key, value) in abbreviations.iter() {
+        println!("{}\t{}", key, value);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shards() {
+        let dir = std::env::temp_dir().join(format!("pubmed-rs-output-{}", std::process::id()));
+        let options = OutputOptions {
+            json: true,
+            output_dir: Some(dir.clone()),
+            shard_articles: Some(2),
+            ..Default::default()
+        };
+        let output = Output::new(options).unwrap();
+        let article = |id: &str| OutputArticle::new(id.to_string());
+        output.add(1, vec![article("3"), article("1")], true);
+        output.add(0, vec![article("2")], true);
+        output.finish(&ExtractOptions::default()).unwrap();
+
+        // Collected articles are sorted by ID before they are split up.
+        let index = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert!(index("part-00000.index") == "1\n2\n");
+        assert!(index("part-00001.index") == "3\n");
+        assert!(index("part-00001.jsonl").lines().count() == 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use anyhow::{anyhow, Result};
+
+// ===========================================================================
+
+/// Which section types are kept. A section type in the allow set is
+/// kept, one in the deny set is dropped, and the others are kept or
+/// dropped depending on keep_others. Allowing a section takes it off
+/// the deny set, and the other way around, so an allow or deny on the
+/// command line overrides the preset or file it starts from.
+///
+/// Policies come as presets, or from a TOML or YAML file:
+///
+/// ```toml
+/// preset = "core"
+/// allow = ["CASE"]
+/// deny = ["METHODS"]
+/// ```
+///
+/// Without a preset, the other sections are dropped when there is an
+/// allow list, and kept otherwise. Set "others" to "keep" or "drop" to
+/// choose yourself.
+#[derive(Debug, Clone)]
+pub struct SectionPolicy {
+    pub allow: BTreeSet<String>,
+    pub deny: BTreeSet<String>,
+    pub keep_others: bool,
+}
+
+// The back matter, and other sections that are not about the research
+// itself. METHODS? Yes, no, maybe?
+const CORE_DENY: [&str; 10] = [
+    "REF", "FIG", "TABLE", "APPENDIX", "COMP_INT", "CASE",
+    "AUTH_CONT", "ACK_FUND", "SUPPL", "REVIEW_INFO",
+];
+
+pub const PRESETS: [&str; 3] = ["core", "abstract-only", "everything"];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    preset: Option<String>,
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+    others: Option<String>,
+}
+
+impl Default for SectionPolicy {
+    fn default() -> Self {
+        SectionPolicy::preset("core").unwrap()
+    }
+}
+
+impl SectionPolicy {
+    pub fn preset(name: &str) -> Option<SectionPolicy> {
+        let (allow, deny, keep_others): (&[&str], &[&str], bool) = match name {
+            "core" => (&[], &CORE_DENY, true),
+            "abstract-only" => (&["ABSTRACT", "ABBR"], &[], false),
+            "everything" => (&[], &[], true),
+            _ => return None,
+        };
+        Some(SectionPolicy {
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            deny: deny.iter().map(|s| s.to_string()).collect(),
+            keep_others,
+        })
+    }
+
+    /// Only these sections, and the abbreviations. This is what the
+    /// --allowed option has always done.
+    pub fn only<I: IntoIterator<Item = String>>(sections: I) -> SectionPolicy {
+        let mut allow: BTreeSet<String> = sections.into_iter().collect();
+        allow.insert("ABBR".to_string());
+        SectionPolicy { allow, deny: BTreeSet::new(), keep_others: false }
+    }
+
+    /// A preset name, or else a .toml, .yaml or .yml policy file.
+    pub fn load(name: &str) -> Result<SectionPolicy> {
+        if let Some(policy) = SectionPolicy::preset(name) {
+            return Ok(policy);
+        }
+        let path = Path::new(name);
+        if !path.is_file() {
+            return Err(anyhow!("unknown section policy {:?}, use one of {} or a policy file",
+                               name, PRESETS.join(", ")));
+        }
+        let data = fs::read_to_string(path)?;
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        let file: PolicyFile = match extension {
+            "toml" => toml::from_str(&data).map_err(|e| anyhow!("{}: {}", name, e))?,
+            "yaml" | "yml" => serde_yaml::from_str(&data).map_err(|e| anyhow!("{}: {}", name, e))?,
+            _ => return Err(anyhow!("{}: policy files should end in .toml, .yaml or .yml", name)),
+        };
+
+        let mut policy = match &file.preset {
+            Some(preset) => SectionPolicy::preset(preset)
+                .ok_or_else(|| anyhow!("{}: unknown preset {:?}, use one of {}", name, preset, PRESETS.join(", ")))?,
+            None => SectionPolicy {
+                allow: BTreeSet::new(),
+                deny: BTreeSet::new(),
+                keep_others: file.allow.is_empty(),
+            },
+        };
+        match file.others.as_deref() {
+            Some("keep") => policy.keep_others = true,
+            Some("drop") => policy.keep_others = false,
+            Some(other) => return Err(anyhow!("{}: others should be \"keep\" or \"drop\", not {:?}", name, other)),
+            None => {}
+        }
+        policy.allow_sections(file.allow);
+        policy.deny_sections(file.deny);
+        Ok(policy)
+    }
+
+    pub fn allow_sections<I: IntoIterator<Item = String>>(&mut self, sections: I) {
+        for section in sections {
+            self.deny.remove(&section);
+            self.allow.insert(section);
+        }
+    }
+
+    pub fn deny_sections<I: IntoIterator<Item = String>>(&mut self, sections: I) {
+        for section in sections {
+            self.allow.remove(&section);
+            self.deny.insert(section);
+        }
+    }
+
+    pub fn allows(&self, section_type: &str) -> bool {
+        if self.allow.contains(section_type) {
+            true
+        } else if self.deny.contains(section_type) {
+            false
+        } else {
+            self.keep_others
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn presets() {
+        let core = SectionPolicy::default();
+        assert!(core.allows("INTRO") && core.allows("ABBR") && !core.allows("REF"));
+
+        let abstracts = SectionPolicy::load("abstract-only").unwrap();
+        assert!(abstracts.allows("ABSTRACT") && abstracts.allows("ABBR") && !abstracts.allows("INTRO"));
+
+        let mut policy = SectionPolicy::load("core").unwrap();
+        policy.allow_sections(["CASE".to_string()]);
+        policy.deny_sections(["METHODS".to_string()]);
+        assert!(policy.allows("CASE") && !policy.allows("METHODS") && policy.allows("RESULTS"));
+
+        assert!(SectionPolicy::load("no-such-preset").is_err());
+    }
+
+    #[test]
+    fn policy_file() {
+        let dir = std::env::temp_dir().join(format!("pubmed-rs-policy-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("policy.yaml");
+        fs::write(&path, "allow: [INTRO, RESULTS]\n").unwrap();
+        let policy = SectionPolicy::load(path.to_str().unwrap()).unwrap();
+        assert!(policy.allows("INTRO") && !policy.allows("ABBR"));
+
+        let path = dir.join("policy.toml");
+        fs::write(&path, "preset = \"everything\"\ndeny = [\"REF\"]\n").unwrap();
+        let policy = SectionPolicy::load(path.to_str().unwrap()).unwrap();
+        assert!(policy.allows("FIG") && !policy.allows("REF"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use zstd::stream::write::Encoder;
+
+// ===========================================================================
+
+/*
+    Sharded output. Records (articles, or abbreviation lines) are
+    written to part-00000.jsonl, part-00001.jsonl, ... in the output
+    directory, starting a new file after a number of articles or
+    bytes. Next to every part there is a part-00000.index with the ids
+    of the records in it, one per line.
+*/
+pub struct ShardWriter {
+    dir: PathBuf,
+    extension: String,
+    compress: bool,
+    max_records: Option<usize>,
+    max_bytes: Option<u64>,
+    number: usize,
+    current: Option<Shard>,
+}
+
+struct Shard {
+    name: String,
+    out: ShardFile,
+    ids: Vec<String>,
+    bytes: u64,
+}
+
+enum ShardFile {
+    Plain(BufWriter<File>),
+    Zstd(Encoder<'static, BufWriter<File>>),
+}
+
+impl ShardWriter {
+    // The extension is the one of the uncompressed files, "jsonl",
+    // "txt" or "tsv". Compressed files get ".zst" added.
+    pub fn new(dir: PathBuf, extension: &str, compress: bool, max_records: Option<usize>, max_bytes: Option<u64>) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(ShardWriter {
+            dir,
+            extension: extension.to_string(),
+            compress,
+            max_records,
+            max_bytes,
+            number: 0,
+            current: None,
+        })
+    }
+
+    pub fn write_record(&mut self, id: &str, record: &[u8]) -> io::Result<()> {
+        // The limits are checked before writing, so a record is never
+        // split over two files, and no empty file is left at the end.
+        let full = self.current.as_ref().is_some_and(|shard| {
+            self.max_records.is_some_and(|max| shard.ids.len() >= max)
+                || self.max_bytes.is_some_and(|max| shard.bytes >= max)
+        });
+        if full {
+            self.close()?;
+        }
+        if self.current.is_none() {
+            self.current = Some(self.open()?);
+        }
+
+        let shard = self.current.as_mut().unwrap();
+        match &mut shard.out {
+            ShardFile::Plain(out) => out.write_all(record)?,
+            ShardFile::Zstd(out) => out.write_all(record)?,
+        }
+        shard.ids.push(id.to_string());
+        shard.bytes += record.len() as u64;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.close()
+    }
+
+    fn open(&mut self) -> io::Result<Shard> {
+        let mut name = format!("part-{:05}.{}", self.number, self.extension);
+        if self.compress {
+            name.push_str(".zst");
+        }
+        self.number += 1;
+
+        let file = BufWriter::new(File::create(self.dir.join(&name))?);
+        let out = if self.compress {
+            ShardFile::Zstd(Encoder::new(file, 0)?)
+        } else {
+            ShardFile::Plain(file)
+        };
+        Ok(Shard { name, out, ids: vec![], bytes: 0 })
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        let Some(shard) = self.current.take() else {
+            return Ok(());
+        };
+        match shard.out {
+            ShardFile::Plain(mut out) => out.flush()?,
+            ShardFile::Zstd(out) => out.finish()?.flush()?,
+        }
+
+        let stem = shard.name.split('.').next().unwrap();
+        let mut index = BufWriter::new(File::create(self.dir.join(format!("{}.index", stem)))?);
+        for id in &shard.ids {
+            writeln!(index, "{}", id)?;
+        }
+        index.flush()
+    }
+}
+
+// Sizes like 500000, 64K, 500M or 2G.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let (number, factor) = match size.chars().last() {
+        Some('K') | Some('k') => (&size[..size.len() - 1], 1 << 10),
+        Some('M') | Some('m') => (&size[..size.len() - 1], 1 << 20),
+        Some('G') | Some('g') => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+    let n = number.parse::<u64>()
+        .map_err(|_| format!("invalid size {:?}, use a number with an optional K, M or G", size))?;
+    n.checked_mul(factor)
+        .ok_or_else(|| format!("size {:?} is too large", size))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert!(parse_size("500M") == Ok(500 << 20));
+        assert!(parse_size("2g") == Ok(2 << 30));
+        assert!(parse_size("1000") == Ok(1000));
+        assert!(parse_size("99999999999G").is_err());
+        assert!(parse_size("lots").is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use anyhow::{anyhow, Result};
+
+use crate::xml::{self, Element};
+
+// ===========================================================================
+
+/// Tables rebuilt from the markup in the "xml" infon of the BioC table
+/// passages, the flattened text of the passage loses the rows and
+/// columns. The JATS reader adds the same infon.
+///
+/// Cells spanning more rows or columns are repeated in every row and
+/// column they cover, so all rows have the same number of cells. The
+/// header rows are the ones in `<thead>`, or without a `<thead>`, the rows
+/// at the top that have only `<th>` cells.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+pub struct Table {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub caption: Option<String>,
+    #[serde(default)]
+    pub header: Vec<Vec<String>>,
+    pub rows: Vec<Vec<String>>,
+}
+
+// Spans above a thousand are mistakes, and would blow up the table.
+const MAX_SPAN: usize = 1000;
+
+impl Table {
+    /// The first `<table>` in the markup, which can be the root itself or
+    /// be wrapped in a `<table-wrap>`.
+    pub fn from_xml(data: &str) -> Result<Table> {
+        let root = xml::parse(data)?;
+        if root.name == "table" {
+            return Ok(Table::from_element(&root));
+        }
+        let mut tables = vec![];
+        root.find_all("table", &mut tables);
+        tables.first()
+            .map(|table| Table::from_element(table))
+            .ok_or_else(|| anyhow!("no <table> in the table markup"))
+    }
+
+    pub fn from_element(table: &Element) -> Table {
+        let mut rows: Vec<(bool, &Element, &Element)> = vec![];
+        collect_rows(table, false, &mut rows);
+        let has_thead = rows.iter().any(|(thead, _, _)| *thead);
+
+        // Per column, the cell that spans into the next rows and for how
+        // many rows more.
+        let mut spans: Vec<(usize, String)> = vec![];
+        let mut header = vec![];
+        let mut body = vec![];
+        let mut leading = true;
+        let mut previous_group: Option<&Element> = None;
+
+        for (thead, group, tr) in rows {
+            // Rows don't span from the <thead> into the <tbody>.
+            if previous_group.is_some_and(|previous| !std::ptr::eq(previous, group)) {
+                spans.clear();
+            }
+            previous_group = Some(group);
+
+            let cells: Vec<&Element> = tr.elements().filter(|e| e.name == "th" || e.name == "td").collect();
+            if cells.is_empty() {
+                continue;
+            }
+            let is_header = thead || (!has_thead && leading && cells.iter().all(|cell| cell.name == "th"));
+            leading = is_header;
+
+            let mut row = vec![];
+            let mut cells = cells.into_iter();
+            let mut column = 0;
+            loop {
+                if column < spans.len() && spans[column].0 > 0 {
+                    spans[column].0 -= 1;
+                    row.push(spans[column].1.clone());
+                    column += 1;
+                    continue;
+                }
+                let Some(cell) = cells.next() else {
+                    break;
+                };
+                let text = cell.clean_text();
+                let colspan = span(cell, "colspan");
+                let rowspan = span(cell, "rowspan");
+                let mut placed = 0;
+                while placed < colspan {
+                    // A column in the span can still be taken by a cell
+                    // from the rows above, which keeps it.
+                    if column < spans.len() && spans[column].0 > 0 {
+                        spans[column].0 -= 1;
+                        row.push(spans[column].1.clone());
+                        column += 1;
+                        continue;
+                    }
+                    if spans.len() <= column {
+                        spans.resize(column + 1, (0, String::new()));
+                    }
+                    spans[column] = (rowspan - 1, text.clone());
+                    row.push(text.clone());
+                    column += 1;
+                    placed += 1;
+                }
+            }
+            // Cells from the rows above that span past the last cell.
+            while column < spans.len() {
+                if spans[column].0 > 0 {
+                    spans[column].0 -= 1;
+                    row.push(spans[column].1.clone());
+                } else {
+                    row.push(String::new());
+                }
+                column += 1;
+            }
+
+            if is_header {
+                header.push(row);
+            } else {
+                body.push(row);
+            }
+        }
+
+        let width = header.iter().chain(&body).map(|row| row.len()).max().unwrap_or(0);
+        for row in header.iter_mut().chain(body.iter_mut()) {
+            row.resize(width, String::new());
+        }
+        Table { header, rows: body, ..Default::default() }
+    }
+
+    pub fn columns(&self) -> usize {
+        self.header.first().or(self.rows.first()).map_or(0, |row| row.len())
+    }
+
+    /// The header rows and then the others, quoted where needed.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        for row in self.header.iter().chain(&self.rows) {
+            let cells: Vec<String> = row.iter().map(|cell| csv_field(cell)).collect();
+            writeln!(csv, "{}", cells.join(",")).unwrap();
+        }
+        csv
+    }
+}
+
+// The <tr> elements in order, whether they are in the <thead>, and the
+// row group they are in, or the table itself.
+fn collect_rows<'a>(element: &'a Element, thead: bool, rows: &mut Vec<(bool, &'a Element, &'a Element)>) {
+    for e in element.elements() {
+        match e.name.as_str() {
+            "tr" => rows.push((thead, element, e)),
+            "thead" => collect_rows(e, true, rows),
+            "tbody" | "tfoot" => collect_rows(e, thead, rows),
+            _ => (),
+        }
+    }
+}
+
+fn span(cell: &Element, name: &str) -> usize {
+    cell.attr(name)
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(1)
+        .clamp(1, MAX_SPAN)
+}
+
+fn csv_field(cell: &str) -> String {
+    if cell.contains(['"', ',', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn spans() {
+        let table = Table::from_xml(r#"<?xml version="1.0"?>
+            <table><thead><tr><th rowspan="2">Group</th><th colspan="2">Weight</th></tr>
+            <tr><th>Before</th><th>After</th></tr></thead>
+            <tbody><tr><td>A</td><td>1,5</td><td>2</td></tr>
+            <tr><td>B</td><td colspan="2">"n/a"</td></tr></tbody></table>"#).unwrap();
+        assert!(table.header == [["Group", "Weight", "Weight"], ["Group", "Before", "After"]]);
+        assert!(table.rows == [["A", "1,5", "2"], ["B", "\"n/a\"", "\"n/a\""]]);
+        assert!(table.columns() == 3);
+        assert!(table.to_csv().lines().nth(2) == Some("A,\"1,5\",2"));
+    }
+
+    #[test]
+    fn leading_th() {
+        let table = Table::from_xml("<table-wrap><table><tr><th>a</th><th>b</th></tr>\
+                                     <tr><td>1</td></tr><tr><th>c</th></tr></table></table-wrap>").unwrap();
+        assert!(table.header == [["a", "b"]]);
+        assert!(table.rows == [["1", ""], ["c", ""]]);
+        assert!(Table::from_xml("<p>no table</p>").is_err());
+    }
+
+    #[test]
+    fn colspan_next_to_rowspan() {
+        let table = Table::from_xml("<table><thead><tr><th rowspan=\"2\">H</th><th>x</th></tr></thead>\
+                                     <tbody><tr><td>a</td><td rowspan=\"2\">b</td><td>c</td></tr>\
+                                     <tr><td colspan=\"2\">d</td><td>e</td></tr></tbody></table>").unwrap();
+        // The rowspan in the header stops at the <tbody>.
+        assert!(table.header == [["H", "x", "", ""]]);
+        // The b from above keeps its column, the colspan goes around it.
+        assert!(table.rows == [["a", "b", "c", ""], ["d", "b", "d", "e"]]);
+    }
+}
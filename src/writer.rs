@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufWriter, Stdout, Write};
+use std::sync::mpsc::{sync_channel, SendError, SyncSender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::json::OutputArticle;
+use crate::shard::ShardWriter;
+
+// ===========================================================================
+
+/*
+    Streaming output. The articles of every file are sent to a writer
+    thread in batches as they are read, together with the sequence
+    number of the file and whether it is the last batch of the file.
+    Unless we want sorted output they are written straight away,
+    otherwise they wait in a reorder buffer until all the files before
+    them are done. The file whose turn it is is written as it comes.
+*/
+pub type Batch = (usize, Vec<OutputArticle>, bool);
+
+// Where the output goes: standard out, or shard files in a directory.
+pub enum Destination {
+    Stdout(BufWriter<Stdout>),
+    Shards(ShardWriter),
+}
+
+impl Destination {
+    pub fn stdout() -> Self {
+        Destination::Stdout(BufWriter::new(io::stdout()))
+    }
+
+    // The id is used for the shard index. Articles with nothing to
+    // write, like those without tables when only the tables are written,
+    // are left out of the index.
+    pub fn write_record(&mut self, id: &str, record: &[u8]) -> io::Result<()> {
+        if record.is_empty() {
+            return Ok(());
+        }
+        match self {
+            Destination::Stdout(out) => out.write_all(record),
+            Destination::Shards(shards) => shards.write_record(id, record),
+        }
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Destination::Stdout(mut out) => out.flush(),
+            Destination::Shards(shards) => shards.finish(),
+        }
+    }
+}
+
+// Batches on their way to the writer. When it can't keep up, the
+// workers wait, instead of the batches piling up in memory.
+const QUEUE: usize = 64;
+
+// With sorted output, the articles waiting in the reorder buffer
+// before the workers on files further on wait for the file whose turn
+// it is, so one slow early file doesn't make the buffer grow without
+// limit.
+const MAX_BUFFERED: usize = 100_000;
+
+// The next file to write, the number of articles in the reorder buffer,
+// and whether the writer has stopped.
+#[derive(Default)]
+struct Progress {
+    next: usize,
+    buffered: usize,
+    stopped: bool,
+}
+
+type Shared = Arc<(Mutex<Progress>, Condvar)>;
+
+pub struct BatchSender {
+    sender: SyncSender<Batch>,
+    progress: Option<Shared>,
+}
+
+impl BatchSender {
+    // Blocks while the queue is full, or with sorted output, while the
+    // reorder buffer is full and it isn't the turn of this file.
+    pub fn send(&self, batch: Batch) -> Result<(), SendError<Batch>> {
+        if let Some(progress) = &self.progress {
+            let (lock, changed) = &**progress;
+            let mut state = lock.lock().unwrap();
+            while batch.0 != state.next && state.buffered >= MAX_BUFFERED && !state.stopped {
+                state = changed.wait(state).unwrap();
+            }
+        }
+        self.sender.send(batch)
+    }
+}
+
+fn update(progress: &Shared, update: impl FnOnce(&mut Progress)) {
+    let (lock, changed) = &**progress;
+    update(&mut lock.lock().unwrap());
+    changed.notify_all();
+}
+
+// format() turns an article into the bytes of its record.
+pub fn start_writer<F>(sorted: bool, mut destination: Destination, mut format: F) -> (BatchSender, JoinHandle<io::Result<()>>)
+where
+    F: FnMut(&mut Vec<u8>, &OutputArticle) -> io::Result<()> + Send + 'static
+{
+    let (sender, receiver) = sync_channel::<Batch>(QUEUE);
+    let progress: Shared = Arc::default();
+    let shared = progress.clone();
+
+    let writer = thread::spawn(move || {
+        let mut record = Vec::new();
+        let mut write = |article: &OutputArticle| {
+            record.clear();
+            format(&mut record, article)?;
+            destination.write_record(article.id(), &record)
+        };
+
+        // The articles of every file that isn't written yet, and whether
+        // all of them have arrived.
+        let mut pending: BTreeMap<usize, (Vec<OutputArticle>, bool)> = BTreeMap::new();
+        let mut next = 0;
+
+        let written = || -> io::Result<()> {
+            for (seq, articles, last) in receiver {
+                if !sorted {
+                    for article in &articles {
+                        write(article)?;
+                    }
+                    continue;
+                }
+                // The articles of the file whose turn it is don't count,
+                // they are written straight away.
+                let added = if seq == next { 0 } else { articles.len() };
+                let entry = pending.entry(seq).or_default();
+                entry.0.extend(articles);
+                entry.1 = last;
+                let mut removed = 0;
+                while let Some((articles, done)) = pending.get_mut(&next) {
+                    for article in articles.drain(..) {
+                        write(&article)?;
+                    }
+                    if !*done {
+                        break;
+                    }
+                    pending.remove(&next);
+                    next += 1;
+                    removed += pending.get(&next).map_or(0, |(articles, _)| articles.len());
+                }
+                update(&shared, |state| {
+                    state.next = next;
+                    state.buffered = state.buffered + added - removed;
+                });
+            }
+
+            // Only left over if a sequence number never arrived.
+            for (articles, _) in std::mem::take(&mut pending).into_values() {
+                for article in &articles {
+                    write(article)?;
+                }
+            }
+            Ok(())
+        };
+        let result = written();
+
+        // Workers waiting for their turn would wait forever.
+        update(&shared, |state| state.stopped = true);
+        result.and(destination.finish())
+    });
+
+    (BatchSender { sender, progress: sorted.then_some(progress) }, writer)
+}
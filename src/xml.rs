@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, BytesText, Event};
+use quick_xml::Reader;
+
+use anyhow::{anyhow, Result};
+
+// ===========================================================================
+
+// A small element tree on top of quick-xml. A single article is small
+// enough to keep in memory, and walking a tree is a lot easier than
+// juggling events in every reader.
+#[derive(Debug, Default)]
+pub struct Element {
+    pub name: String,
+    pub attrs: HashMap<String, String>,
+    pub children: Vec<Node>,
+}
+
+#[derive(Debug)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    pub fn attr(&self, key: &str) -> Option<&str> {
+        self.attrs.get(key).map(|v| v.as_str())
+    }
+
+    // All child elements, in document order.
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|c| match c {
+            Node::Element(e) => Some(e),
+            Node::Text(_) => None,
+        })
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.elements().filter(move |e| e.name == name)
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|e| e.name == name)
+    }
+
+    // Follows a path of child names, e.g. ["front", "article-meta"].
+    pub fn path(&self, names: &[&str]) -> Option<&Element> {
+        let mut current = self;
+        for name in names {
+            current = current.child(name)?;
+        }
+        Some(current)
+    }
+
+    // All descendants with this name, without descending into matches.
+    pub fn find_all<'a>(&'a self, name: &str, found: &mut Vec<&'a Element>) {
+        for e in self.elements() {
+            if e.name == name {
+                found.push(e);
+            } else {
+                e.find_all(name, found);
+            }
+        }
+    }
+
+    // The concatenated text of the element and all its descendants.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        self.collect_text(&mut text);
+        text
+    }
+
+    fn collect_text(&self, text: &mut String) {
+        for c in &self.children {
+            match c {
+                Node::Text(t) => text.push_str(t),
+                Node::Element(e) => e.collect_text(text),
+            }
+        }
+    }
+
+    // Text with the whitespace runs collapsed to a single space, which
+    // is what we want from pretty-printed XML.
+    pub fn clean_text(&self) -> String {
+        collapse_whitespace(&self.text())
+    }
+
+    // The element as markup again, like BioC keeps tables in an infon.
+    // The attributes are sorted, to give the same markup every time.
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::new();
+        self.write_xml(&mut xml);
+        xml
+    }
+
+    fn write_xml(&self, xml: &mut String) {
+        xml.push('<');
+        xml.push_str(&self.name);
+        let mut attrs: Vec<_> = self.attrs.iter().collect();
+        attrs.sort();
+        for (key, value) in attrs {
+            xml.push_str(&format!(" {}=\"{}\"", key, escape(value)));
+        }
+        if self.children.is_empty() {
+            xml.push_str("/>");
+            return;
+        }
+        xml.push('>');
+        for c in &self.children {
+            match c {
+                Node::Text(t) => xml.push_str(&escape(t)),
+                Node::Element(e) => e.write_xml(xml),
+            }
+        }
+        xml.push_str("</");
+        xml.push_str(&self.name);
+        xml.push('>');
+    }
+}
+
+pub fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Parse a complete document and return the root element.
+pub fn parse(data: &str) -> Result<Element> {
+    let mut reader = Reader::from_str(data);
+    let mut buf = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(start) => {
+                let start = start.into_owned();
+                return read_element(&mut reader, &start, &mut buf);
+            },
+            Event::Empty(start) => return start_element(&start),
+            Event::Eof => return Err(anyhow!("no root element found")),
+            _ => (),
+        }
+        buf.clear();
+    }
+}
+
+// Read the rest of an element whose start tag has just been read.
+// This lets the streaming readers pick single records out of very
+// large files.
+pub fn read_element<R: BufRead>(reader: &mut Reader<R>, start: &BytesStart, buf: &mut Vec<u8>) -> Result<Element> {
+    let mut stack = vec![start_element(start)?];
+
+    loop {
+        buf.clear();
+        match reader.read_event_into(buf)? {
+            Event::Start(s) => stack.push(start_element(&s)?),
+            Event::Empty(s) => {
+                let e = start_element(&s)?;
+                stack.last_mut().unwrap().children.push(Node::Element(e));
+            },
+            Event::Text(t) => {
+                let text = unescape(&t);
+                stack.last_mut().unwrap().children.push(Node::Text(text));
+            },
+            Event::CData(t) => {
+                let text = String::from_utf8_lossy(&t).into_owned();
+                stack.last_mut().unwrap().children.push(Node::Text(text));
+            },
+            Event::End(_) => {
+                let e = stack.pop().unwrap();
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(Node::Element(e)),
+                    None => return Ok(e),
+                }
+            },
+            Event::Eof => return Err(anyhow!("unexpected end of file inside <{}>", stack[0].name)),
+            _ => (),
+        }
+    }
+}
+
+fn start_element(start: &BytesStart) -> Result<Element> {
+    let name = String::from_utf8_lossy(start.name().as_ref()).into_owned();
+    let mut attrs = HashMap::new();
+    for attr in start.attributes() {
+        let attr = attr?;
+        let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+        let value = unescape_str(&String::from_utf8_lossy(&attr.value));
+        attrs.insert(key, value);
+    }
+    Ok(Element { name, attrs, children: vec![] })
+}
+
+// Some files refer to a handful of HTML entities from their DTD, which
+// quick-xml doesn't know about. An entity we cannot resolve is kept
+// as-is, the ones around it are still resolved.
+fn unescape(text: &BytesText) -> String {
+    unescape_str(&String::from_utf8_lossy(text))
+}
+
+fn unescape_str(raw: &str) -> String {
+    let mut text = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(amp) = rest.find('&') {
+        text.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let resolved = rest.find(';')
+            .and_then(|semicolon| Some((resolve_entity(&rest[1..semicolon])?, semicolon)));
+        match resolved {
+            Some((c, semicolon)) => {
+                text.push(c);
+                rest = &rest[semicolon + 1..];
+            },
+            None => {
+                text.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+fn resolve_entity(entity: &str) -> Option<char> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix('x') {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        "thinsp" => Some('\u{2009}'),
+        "ndash" => Some('\u{2013}'),
+        "mdash" => Some('\u{2014}'),
+        "hellip" => Some('\u{2026}'),
+        "times" => Some('\u{d7}'),
+        "plusmn" => Some('\u{b1}'),
+        "deg" => Some('\u{b0}'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn entities() {
+        assert!(unescape_str("a &amp; b &lt; c&#x3b1;&#946; 5&plusmn;1") == "a & b < c\u{3b1}\u{3b2} 5\u{b1}1");
+        // An unknown entity doesn't keep the others escaped.
+        assert!(unescape_str("&alpha; &amp; &lt;&unknown;") == "&alpha; & <&unknown;");
+        assert!(unescape_str("AT&T; 3 & 4") == "AT&T; 3 & 4");
+    }
+}